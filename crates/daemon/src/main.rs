@@ -1,8 +1,13 @@
+mod pool;
+
 use std::iter::Iterator;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use wasm_central_runner::functions::{FunctionManager, FunctionStatus};
+use std::sync::Arc;
+use std::collections::HashMap;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use wasm_central_runner::functions::{FunctionManager, FunctionManagerError, FunctionStatus, Module, ModuleHandle};
+use wasm_central_runner::limits::ExecutionLimits;
+use wasm_central_runner::runner::ExecutionError;
 
 use std::vec::Vec;
 
@@ -12,11 +17,20 @@ use tonic::{transport::Server, Request, Response, Status, Streaming};
 
 use iter_tools::Itertools;
 use prost::Message;
+use std::io;
 use std::io::{Read, Write};
+use std::pin::Pin;
+use tokio::sync::{mpsc, Semaphore};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
 use std::{fs, str, thread};
 use std::fmt::format;
 use zip::write::FileOptions;
-use wasm_central_runner::data::DataFrame;
+use wasm_central_runner::data::{ChunkReader, ChunkWriter, DataFrame, Invocation, Metadata};
+use wasm_central_runner::routing::Shadow;
+use wasm_central_runner::{bundle, routing, schema, shadow};
+
+use crate::pool::{ExecutionPool, PoolError};
 
 use crate::fn_proto::executor_server::Executor;
 use crate::fn_proto::executor_server::ExecutorServer;
@@ -33,8 +47,44 @@ struct Cli {
     /// Port to listen to
     port: u16,
     modules_path: PathBuf,
+    /// Fuel units a function can consume per execution
+    #[clap(long)]
+    fuel: Option<u64>,
+    /// Milliseconds a function can run for per execution
+    #[clap(long)]
+    timeout_ms: Option<u64>,
+    /// Bytes a function linear memory can grow to
+    #[clap(long)]
+    max_memory_bytes: Option<usize>,
+    /// Elements a function table can grow to
+    #[clap(long)]
+    max_table_elements: Option<u32>,
+    /// Instances a function execution can create
+    #[clap(long)]
+    max_instances: Option<usize>,
+    /// Threads running function executions
+    #[clap(long, default_value_t = 4)]
+    workers: usize,
+    /// Executions waiting for a free worker before new ones are rejected, at least 1
+    #[clap(long, default_value_t = 64)]
+    queue_size: usize,
+    /// Run functions as async wasmtime calls on the server runtime instead of the worker pool
+    #[clap(long)]
+    async_execution: bool,
+    /// Directory where the stderr of every function gets appended, one file per function
+    #[clap(long)]
+    guest_log_dir: Option<PathBuf>,
 }
 
+/// Output chunks buffered per streamed execution before the guest blocks on its stdout
+const STREAM_REPLY_BUFFER: usize = 16;
+/// Input chunks buffered per streamed execution before the client stream stops being polled
+const STREAM_INPUT_BUFFER: usize = 16;
+/// Threads running mirrored and shadowed calls, apart from the ones running live calls
+const BACKGROUND_WORKERS: usize = 1;
+/// Mirrored and shadowed calls queued or running at once, the ones beyond being dropped
+const BACKGROUND_RUNS: usize = 8;
+
 pub mod fn_proto {
     tonic::include_proto!("fn_proto");
 }
@@ -43,13 +93,260 @@ pub mod mgmt_proto {
     tonic::include_proto!("mgmt_proto");
 }
 
+#[derive(Clone)]
+pub enum ExecutionMode {
+    /// Executions queue for a thread of the blocking pool, mirrored and shadowed calls for one of
+    /// the second pool so they never hold back live calls
+    Pool(Arc<ExecutionPool>, Arc<ExecutionPool>),
+    /// Executions run on the tokio runtime, yielding to it as they consume fuel. Mirrored and
+    /// shadowed calls each take one of the semaphore permits, being dropped when none is left
+    Async(Arc<Semaphore>),
+}
+
 pub struct Impl {
-    manager: Arc<Mutex<FunctionManager>>,
+    manager: Arc<FunctionManager>,
+    mode: ExecutionMode,
 }
 
 impl Impl {
-    pub fn new(manager: Arc<Mutex<FunctionManager>>) -> Impl {
-        Impl { manager }
+    pub fn new(manager: Arc<FunctionManager>, mode: ExecutionMode) -> Impl {
+        Impl { manager, mode }
+    }
+
+    /// Runs a mirrored version in the background, only its metrics keep track of the outcome.
+    /// Dropped when the background capacity is used up
+    fn mirror(&self, mirror: Option<ModuleHandle>, frames: Vec<DataFrame>) {
+        let mirror = match mirror {
+            Some(mirror) => mirror,
+            None => return,
+        };
+        let target = format!("{}@{}", mirror.name, mirror.version);
+        match &self.mode {
+            ExecutionMode::Pool(_, background) => {
+                let background = background.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = background.run(move || mirror.run_batch(&frames)).await {
+                        eprintln!("Dropped mirrored call to fn {} because {:?}", target, err);
+                    }
+                });
+            }
+            ExecutionMode::Async(background) => match background.clone().try_acquire_owned() {
+                Ok(permit) => {
+                    tokio::spawn(async move {
+                        mirror.run_batch_async(&frames).await;
+                        drop(permit);
+                    });
+                }
+                Err(_) => eprintln!("Dropped mirrored call to fn {} because of too many background runs", target),
+            },
+        }
+    }
+
+    /// Runs the shadowing version in the background on the frames the active one succeeded on,
+    /// recording how both outputs differ
+    fn shadow(&self, shadow: Option<Shadow>, frames: Vec<DataFrame>, invocations: &[Invocation]) {
+        let shadow = match shadow {
+            Some(shadow) => shadow,
+            None => return,
+        };
+        let comparisons = frames
+            .into_iter()
+            .zip(invocations)
+            .filter_map(|(frame, invocation)| {
+                let output = invocation.result.as_ref().ok()?.clone();
+                Some((frame, invocation.id, output))
+            })
+            .collect::<Vec<(DataFrame, u64, DataFrame)>>();
+        if comparisons.is_empty() {
+            return;
+        }
+        let target = format!("{}@{}", shadow.handle.name, shadow.handle.version);
+        match &self.mode {
+            ExecutionMode::Pool(_, background) => {
+                let background = background.clone();
+                tokio::spawn(async move {
+                    let compare = move || {
+                        for (frame, invocation_id, output) in &comparisons {
+                            shadow.compare(frame, *invocation_id, output);
+                        }
+                    };
+                    if let Err(err) = background.run(compare).await {
+                        eprintln!("Dropped shadowed call to fn {} because {:?}", target, err);
+                    }
+                });
+            }
+            ExecutionMode::Async(background) => match background.clone().try_acquire_owned() {
+                Ok(permit) => {
+                    tokio::spawn(async move {
+                        for (frame, invocation_id, output) in &comparisons {
+                            shadow.compare_async(frame, *invocation_id, output).await;
+                        }
+                        drop(permit);
+                    });
+                }
+                Err(_) => eprintln!("Dropped shadowed call to fn {} because of too many background runs", target),
+            },
+        }
+    }
+}
+
+fn runner_schema(schema: Schema) -> schema::Schema {
+    let format = match SchemaFormat::from_i32(schema.format) {
+        Some(SchemaFormat::Protobuf) => schema::SchemaFormat::Protobuf,
+        Some(SchemaFormat::JsonSchema) => schema::SchemaFormat::JsonSchema,
+        _ => schema::SchemaFormat::Avro,
+    };
+    let records = schema
+        .records
+        .into_iter()
+        .map(|record| schema::Record {
+            name: record.name,
+            fields: record
+                .fields
+                .into_iter()
+                .map(|field| schema::Field {
+                    name: field.name,
+                    field_type: field.r#type,
+                    number: field.number,
+                })
+                .collect(),
+        })
+        .collect();
+    schema::Schema { format, records }
+}
+
+fn metadata(
+    sender: String,
+    schema: Option<Schema>,
+    headers: HashMap<String, String>,
+    content_type: Option<String>,
+    deadline_ms: u64,
+    entrypoint: String,
+) -> Metadata {
+    Metadata {
+        sender,
+        headers: headers.into_iter().collect(),
+        content_type,
+        schema: schema.map(runner_schema),
+        invocation_id: 0,
+        deadline: (deadline_ms > 0).then(|| UNIX_EPOCH + Duration::from_millis(deadline_ms)),
+        entrypoint: (!entrypoint.is_empty()).then_some(entrypoint),
+    }
+}
+
+fn execute_code(err: &ExecutionError) -> ExecuteCode {
+    match err {
+        ExecutionError::ModuleNotFound(_) => ExecuteCode::ModuleNotFound,
+        ExecutionError::NotDeployed(_) => ExecuteCode::NotDeployed,
+        ExecutionError::InvalidInput(_) => ExecuteCode::InvalidInput,
+        ExecutionError::InvalidOutput(_) => ExecuteCode::InvalidOutput,
+        ExecutionError::Trap { .. } => ExecuteCode::Trapped,
+        ExecutionError::NonZeroExit(_) => ExecuteCode::NonZeroExit,
+        ExecutionError::FuelExhausted(_) => ExecuteCode::FuelExhausted,
+        ExecutionError::Timeout(_) => ExecuteCode::Timeout,
+        ExecutionError::MemoryLimitExceeded(_) => ExecuteCode::MemoryLimitExceeded,
+        ExecutionError::InvalidModule(_) => ExecuteCode::InvalidModule,
+        ExecutionError::Io(_) | ExecutionError::Failure(_) => ExecuteCode::Failed,
+    }
+}
+
+fn execute_error(err: &ExecutionError) -> ExecuteError {
+    let mut error = ExecuteError {
+        code: execute_code(err) as i32,
+        message: err.to_string(),
+        backtrace: vec![],
+        trap_code: None,
+        exit_code: None,
+        retryable: err.is_retryable(),
+    };
+    match err {
+        ExecutionError::Trap {
+            trap_code,
+            backtrace,
+            ..
+        } => {
+            error.trap_code = trap_code.map(|trap_code| format!("{:?}", trap_code));
+            error.backtrace = backtrace.clone();
+        }
+        ExecutionError::NonZeroExit(exit_code) => error.exit_code = Some(*exit_code),
+        _ => {}
+    }
+    error
+}
+
+fn error_reply(err: &ExecutionError, stderr: Vec<u8>) -> ExecuteReply {
+    let error = execute_error(err);
+    ExecuteReply {
+        code: error.code,
+        body: error.message.clone().into_bytes(),
+        stderr,
+        error: Some(error),
+        content_type: None,
+    }
+}
+
+fn execute_reply(invocation: Invocation) -> ExecuteReply {
+    match invocation.result {
+        Ok(output) => {
+            println!("Executed function");
+            ExecuteReply {
+                code: ExecuteCode::Ok as i32,
+                body: output.body,
+                stderr: invocation.stderr,
+                error: None,
+                content_type: output.metadata.content_type,
+            }
+        }
+        Err(err) => {
+            eprintln!("Error executing function: {:?}", err);
+            error_reply(&err, invocation.stderr)
+        }
+    }
+}
+
+/// For calls made of several records, a function that cannot run fails the whole call
+fn handle_status(err: ExecutionError) -> Status {
+    match err {
+        ExecutionError::ModuleNotFound(_) => Status::not_found(err.to_string()),
+        ExecutionError::NotDeployed(_) => Status::unavailable(err.to_string()),
+        _ => Status::internal(err.to_string()),
+    }
+}
+
+fn version_metrics(module: &Module) -> VersionMetrics {
+    let metrics = module.metrics.snapshot();
+    VersionMetrics {
+        version: module.version.clone(),
+        successes: metrics.successes as i64,
+        failures: metrics.failures as i64,
+        total_messages: metrics.total_messages as i64,
+        fail_rate_per_minute: metrics.fail_rate_per_minute,
+        latency: Some(LatencyPercentiles {
+            p50_ms: metrics.latency.p50_ms,
+            p90_ms: metrics.latency.p90_ms,
+            p99_ms: metrics.latency.p99_ms,
+        }),
+    }
+}
+
+fn difference(difference: shadow::Difference) -> Difference {
+    let kind = match difference.kind {
+        shadow::DifferenceKind::Removed => DifferenceKind::Removed,
+        shadow::DifferenceKind::Added => DifferenceKind::Added,
+        shadow::DifferenceKind::Changed => DifferenceKind::Changed,
+    };
+    Difference {
+        path: difference.path,
+        kind: kind as i32,
+        active: difference.active.map(|value| value.to_string()),
+        candidate: difference.candidate.map(|value| value.to_string()),
+    }
+}
+
+fn pool_status(err: PoolError) -> Status {
+    match err {
+        PoolError::QueueFull => Status::resource_exhausted("Execution queue is full"),
+        PoolError::Stopped => Status::unavailable("Execution pool is stopped"),
     }
 }
 
@@ -60,30 +357,136 @@ impl Executor for Impl {
         request: Request<ExecuteRequest>,
     ) -> Result<Response<ExecuteReply>, Status> {
         let req = request.into_inner();
-        if let Some(handle) = self.manager.lock()
-            .unwrap()
-            .get_handle(&req.name) {
-            match handle.run(&DataFrame {
-                body: req.body
-            }) {
-                Ok(output) => {
-                    println!("Executed function");
-                    Ok(Response::new(ExecuteReply {
-                        code: 0,
-                        body: output.body
-                    }))
-                }
-                Err(err) => {
-                    eprintln!("Error executing function");
-                    Err(Status::internal(format!("{:?}", err)))
-                }
+        let route = match self.manager.route(&req.name) {
+            Ok(route) => route,
+            Err(err) => return Ok(Response::new(error_reply(&err, vec![]))),
+        };
+        let frame = DataFrame {
+            body: req.body,
+            metadata: metadata(req.sender, req.schema, req.headers, req.content_type, req.deadline_ms, req.entrypoint),
+        };
+        let mirror_frames = route.mirror.as_ref().map(|_| vec![frame.clone()]).unwrap_or_default();
+        let shadow_frames = route.shadow.as_ref().map(|_| vec![frame.clone()]).unwrap_or_default();
+        let handle = route.handle;
+        let invocation = match &self.mode {
+            ExecutionMode::Pool(pool, _) => pool
+                .run(move || handle.run(&frame))
+                .await
+                .map_err(pool_status)?,
+            ExecutionMode::Async(_) => handle.run_async(&frame).await,
+        };
+        // submitted once the live call ran, so it never takes its place
+        self.mirror(route.mirror, mirror_frames);
+        self.shadow(route.shadow, shadow_frames, std::slice::from_ref(&invocation));
+        Ok(Response::new(execute_reply(invocation)))
+    }
+
+    async fn execute_batch(
+        &self,
+        request: Request<ExecuteBatchRequest>,
+    ) -> Result<Response<ExecuteBatchReply>, Status> {
+        let req = request.into_inner();
+        let route = self.manager.route(&req.name).map_err(handle_status)?;
+        let metadata = metadata(req.sender, req.schema, req.headers, req.content_type, req.deadline_ms, req.entrypoint);
+        let frames = req
+            .bodies
+            .into_iter()
+            .map(|body| DataFrame {
+                body,
+                metadata: metadata.clone(),
+            })
+            .collect::<Vec<DataFrame>>();
+        let mirror_frames = route.mirror.as_ref().map(|_| frames.clone()).unwrap_or_default();
+        let shadow_frames = route.shadow.as_ref().map(|_| frames.clone()).unwrap_or_default();
+        let handle = route.handle;
+        let invocations = match &self.mode {
+            ExecutionMode::Pool(pool, _) => pool
+                .run(move || handle.run_batch(&frames))
+                .await
+                .map_err(pool_status)?,
+            ExecutionMode::Async(_) => handle.run_batch_async(&frames).await,
+        };
+        self.mirror(route.mirror, mirror_frames);
+        self.shadow(route.shadow, shadow_frames, &invocations);
+        Ok(Response::new(ExecuteBatchReply {
+            replies: invocations.into_iter().map(execute_reply).collect(),
+        }))
+    }
+
+    type ExecuteStreamStream =
+        Pin<Box<dyn Stream<Item = Result<ExecuteChunkReply, Status>> + Send + 'static>>;
+
+    async fn execute_stream(
+        &self,
+        request: Request<Streaming<ExecuteChunk>>,
+    ) -> Result<Response<Self::ExecuteStreamStream>, Status> {
+        let pool = match &self.mode {
+            ExecutionMode::Pool(pool, _) => pool.clone(),
+            ExecutionMode::Async(_) => {
+                return Err(Status::failed_precondition(
+                    "Streaming executions need the worker pool",
+                ))
             }
-        } else {
-            Ok(Response::new(ExecuteReply {
-                code: 1,
-                body: "Couldn't execute fn".to_string().as_bytes().to_vec()
-            }))
-        }
+        };
+        let mut streaming = request.into_inner();
+        let first = streaming
+            .message()
+            .await?
+            .ok_or_else(|| Status::invalid_argument("Empty execution stream"))?;
+        // streamed input cannot be replayed, so the call is never mirrored
+        let handle = self.manager.route(&first.name).map_err(handle_status)?.handle;
+
+        let metadata = metadata(first.sender, first.schema, first.headers, first.content_type, first.deadline_ms, first.entrypoint);
+
+        let (input_sender, input) = ChunkReader::channel(STREAM_INPUT_BUFFER);
+        let _ = input_sender.send(Ok(first.body));
+        // sending blocks while the guest lags behind, so the stream is polled off the runtime
+        let runtime = tokio::runtime::Handle::current();
+        tokio::task::spawn_blocking(move || loop {
+            let chunk = match runtime.block_on(streaming.message()) {
+                Ok(Some(chunk)) => Ok(chunk.body),
+                Ok(None) => break,
+                Err(status) => Err(io::Error::new(io::ErrorKind::ConnectionAborted, status.message().to_owned())),
+            };
+            let failed = chunk.is_err();
+            if input_sender.send(chunk).is_err() || failed {
+                break;
+            }
+        });
+
+        let (reply_sender, reply_receiver) = mpsc::channel(STREAM_REPLY_BUFFER);
+        let output_sender = reply_sender.clone();
+        let output = ChunkWriter::new(move |body| {
+            output_sender
+                .blocking_send(Ok(ExecuteChunkReply {
+                    code: ExecuteCode::Ok as i32,
+                    body,
+                    last: false,
+                    stderr: vec![],
+                    error: None,
+                }))
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+        });
+        tokio::spawn(async move {
+            let last = match pool.run(move || handle.run_stream(&metadata, input, output)).await {
+                Ok(invocation) => Ok(execute_reply(Invocation {
+                    id: invocation.id,
+                    result: invocation.result.map(|_| DataFrame::new(vec![])),
+                    stderr: invocation.stderr,
+                })),
+                Err(err) => Err(pool_status(err)),
+            };
+            let _ = reply_sender
+                .send(last.map(|reply| ExecuteChunkReply {
+                    code: reply.code,
+                    body: reply.body,
+                    last: true,
+                    stderr: reply.stderr,
+                    error: reply.error,
+                }))
+                .await;
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(reply_receiver))))
     }
 }
 
@@ -95,19 +498,36 @@ impl Manager for Impl {
     ) -> Result<Response<ListReply>, Status> {
         let items = self
             .manager
-            .lock()
-            .unwrap()
             .running_modules()
             .iter()
             .map(|loaded_module| {
                 let module_status = loaded_module.status;
+                let metrics = loaded_module.metrics.snapshot();
+                let versions = self.manager.versions(&loaded_module.name);
                 ListReplyItem {
                     name: String::from(&loaded_module.name),
                     status: module_status.as_string(),
-                    successes: 0,
-                    failures: 0,
-                    total_messages: 0,
-                    fail_rate_per_minute: 0.0,
+                    successes: metrics.successes as i64,
+                    failures: metrics.failures as i64,
+                    total_messages: metrics.total_messages as i64,
+                    fail_rate_per_minute: metrics.fail_rate_per_minute,
+                    latency: Some(LatencyPercentiles {
+                        p50_ms: metrics.latency.p50_ms,
+                        p90_ms: metrics.latency.p90_ms,
+                        p99_ms: metrics.latency.p99_ms,
+                    }),
+                    version: loaded_module.version.clone(),
+                    versions: versions.iter().map(|module| module.version.clone()).collect(),
+                    traffic_split: self.manager.traffic_split(&loaded_module.name).map(|split| TrafficSplit {
+                        version: split.version,
+                        percent: split.percent as u32,
+                        mode: match split.mode {
+                            routing::SplitMode::Canary => SplitMode::Canary as i32,
+                            routing::SplitMode::Mirror => SplitMode::Mirror as i32,
+                            routing::SplitMode::Shadow => SplitMode::Shadow as i32,
+                        },
+                    }),
+                    version_metrics: versions.iter().map(version_metrics).collect(),
                 }
             })
             .collect::<Vec<ListReplyItem>>();
@@ -124,9 +544,16 @@ impl Manager for Impl {
         let mut streaming = request.into_inner();
         let success = true;
         let mut module_name = String::new();
-        let rt_path = self.manager.lock().unwrap().watcher.dir.clone();
+        let rt_path = self.manager.watcher.dir.clone();
         if let Some(item) = streaming.message().await? {
-            let full_path = rt_path.join(format!("{}.{}", item.name.clone(), "wasm"));
+            // bundles are told apart from bare binaries by their first bytes, a module switching
+            // between both drops its previous file so the watcher sees a single one
+            let (ext, other_ext) = if bundle::is_bundle(&item.body) { ("zip", "wasm") } else { ("wasm", "zip") };
+            let other_path = rt_path.join(format!("{}.{}", item.name.clone(), other_ext));
+            if other_path.exists() {
+                fs::remove_file(other_path)?;
+            }
+            let full_path = rt_path.join(format!("{}.{}", item.name.clone(), ext));
             let mut file = fs::File::create(full_path.clone())?;
             file.write_all(&item.body)?;
             while let Some(item) = streaming.message().await? {
@@ -143,8 +570,11 @@ impl Manager for Impl {
         } else {
             Some("Cannot load file".to_owned())
         };
-        self.manager.lock().unwrap().tick();
-        let map = self.manager.lock().unwrap().running_modules_map();
+        let manager = self.manager.clone();
+        tokio::task::spawn_blocking(move || manager.tick())
+            .await
+            .map_err(|err| Status::internal(format!("{:?}", err)))?;
+        let map = self.manager.running_modules_map();
         let module_status = map
             .get(module_name.as_str())
             .map(|i| i.status)
@@ -162,12 +592,133 @@ impl Manager for Impl {
         &self,
         request: Request<UnloadRequest>,
     ) -> Result<Response<UnloadReply>, Status> {
-        return Ok(Response::new(UnloadReply {
-            success: false,
-            error_message: None,
-            unloaded_module_name: String::from("proc"),
-            time: 0,
-        }));
+        let req = request.into_inner();
+        let t_now = Instant::now();
+        let result = self.manager.unload(&req.module_name);
+        let time = t_now.elapsed().as_millis() as i64;
+        match result {
+            Ok(_) => Ok(Response::new(UnloadReply {
+                success: true,
+                error_message: None,
+                unloaded_module_name: req.module_name,
+                time,
+            })),
+            Err(FunctionManagerError::UnavailableModule(module_name)) => Err(Status::not_found(
+                format!("Unknown module {}", module_name),
+            )),
+            Err(err) => {
+                eprintln!("Cannot unload module {} because {}", req.module_name, err);
+                Ok(Response::new(UnloadReply {
+                    success: false,
+                    error_message: Some(err.to_string()),
+                    unloaded_module_name: req.module_name,
+                    time,
+                }))
+            }
+        }
+    }
+
+    async fn rollback(
+        &self,
+        request: Request<RollbackRequest>,
+    ) -> Result<Response<RollbackReply>, Status> {
+        let req = request.into_inner();
+        let t_now = Instant::now();
+        let result = self.manager.rollback(&req.module_name);
+        let time = t_now.elapsed().as_millis() as i64;
+        match result {
+            Ok(active_version) => Ok(Response::new(RollbackReply {
+                success: true,
+                error_message: None,
+                active_version,
+                time,
+            })),
+            Err(FunctionManagerError::UnavailableModule(module_name)) => Err(Status::not_found(
+                format!("Unknown module {}", module_name),
+            )),
+            Err(err) => {
+                eprintln!("Cannot roll back module {} because {}", req.module_name, err);
+                let active_version = self
+                    .manager
+                    .running_modules_map()
+                    .get(&req.module_name)
+                    .map(|module| module.version.clone())
+                    .unwrap_or_default();
+                Ok(Response::new(RollbackReply {
+                    success: false,
+                    error_message: Some(err.to_string()),
+                    active_version,
+                    time,
+                }))
+            }
+        }
+    }
+
+    async fn set_traffic_split(
+        &self,
+        request: Request<SetTrafficSplitRequest>,
+    ) -> Result<Response<SetTrafficSplitReply>, Status> {
+        let req = request.into_inner();
+        if req.percent == 0 {
+            self.manager.clear_traffic_split(&req.module_name);
+            return Ok(Response::new(SetTrafficSplitReply {
+                success: true,
+                error_message: None,
+            }));
+        }
+        let mode = match SplitMode::from_i32(req.mode) {
+            Some(SplitMode::Mirror) => routing::SplitMode::Mirror,
+            Some(SplitMode::Shadow) => routing::SplitMode::Shadow,
+            _ => routing::SplitMode::Canary,
+        };
+        let percent = req.percent.min(u8::MAX as u32) as u8;
+        let split = routing::TrafficSplit::new(req.version, percent, mode);
+        match self.manager.set_traffic_split(&req.module_name, split) {
+            Ok(()) => Ok(Response::new(SetTrafficSplitReply {
+                success: true,
+                error_message: None,
+            })),
+            Err(FunctionManagerError::UnavailableModule(module_name)) => Err(Status::not_found(
+                format!("Unknown module {}", module_name),
+            )),
+            Err(err) => Ok(Response::new(SetTrafficSplitReply {
+                success: false,
+                error_message: Some(err.to_string()),
+            })),
+        }
+    }
+
+    async fn get_shadow_report(
+        &self,
+        request: Request<ShadowReportRequest>,
+    ) -> Result<Response<ShadowReportReply>, Status> {
+        let req = request.into_inner();
+        let report = self
+            .manager
+            .shadow_report(&req.module_name)
+            .ok_or_else(|| Status::not_found(format!("No shadow report for module {}", req.module_name)))?;
+        let snapshot = report.snapshot();
+        Ok(Response::new(ShadowReportReply {
+            candidate_version: snapshot.candidate_version,
+            compared: snapshot.compared as i64,
+            identical: snapshot.identical as i64,
+            different: snapshot.different as i64,
+            candidate_failures: snapshot.candidate_failures as i64,
+            skipped: snapshot.skipped as i64,
+            path_counts: snapshot
+                .path_counts
+                .into_iter()
+                .map(|(path, count)| (path, count as i64))
+                .collect(),
+            samples: snapshot
+                .samples
+                .into_iter()
+                .map(|sample| DiffSample {
+                    invocation_id: sample.invocation_id,
+                    differences: sample.differences.into_iter().map(difference).collect(),
+                })
+                .collect(),
+        }))
     }
 }
 
@@ -185,10 +736,31 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let blue = Style::new().blue();
 
-    let mgr = Arc::new(Mutex::new(FunctionManager::new(path.clone())));
+    let limits = ExecutionLimits {
+        fuel: args.fuel,
+        timeout: args.timeout_ms.map(Duration::from_millis),
+        max_memory_bytes: args.max_memory_bytes,
+        max_table_elements: args.max_table_elements,
+        max_instances: args.max_instances,
+    };
+    let (mgr, mode) = if args.async_execution {
+        let background = Arc::new(Semaphore::new(BACKGROUND_RUNS));
+        (FunctionManager::new_async(path.clone(), limits), ExecutionMode::Async(background))
+    } else {
+        if args.queue_size == 0 {
+            return Err("--queue-size has to be at least 1".into());
+        }
+        let pool = Arc::new(ExecutionPool::new(args.workers, args.queue_size));
+        let background = Arc::new(ExecutionPool::new(BACKGROUND_WORKERS, BACKGROUND_RUNS));
+        (FunctionManager::with_limits(path.clone(), limits), ExecutionMode::Pool(pool, background))
+    };
+    let mgr = match args.guest_log_dir {
+        Some(guest_log_dir) => Arc::new(mgr.with_guest_log(guest_log_dir)?),
+        None => Arc::new(mgr),
+    };
 
-    let mgmt_server = ManagerServer::new(Impl::new(mgr.clone()));
-    let executor_server = ExecutorServer::new(Impl::new(mgr.clone()));
+    let mgmt_server = ManagerServer::new(Impl::new(mgr.clone(), mode.clone()));
+    let executor_server = ExecutorServer::new(Impl::new(mgr.clone(), mode));
     let bootstrap_future = Server::builder()
         .add_service(mgmt_server)
         .add_service(executor_server)
@@ -197,7 +769,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mgr = Arc::clone(&mgr);
     thread::spawn(move || loop {
-        mgr.lock().unwrap().tick();
+        mgr.tick();
         thread::sleep(Duration::from_millis(MODULE_MANAGER_LOOP_WAIT));
     });
     bootstrap_future.await?;
@@ -0,0 +1,99 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::sync::oneshot;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(Debug, PartialEq)]
+pub enum PoolError {
+    QueueFull,
+    Stopped,
+}
+
+/// Fixed set of threads running guest executions off the tokio runtime, fed by a bounded queue
+pub struct ExecutionPool {
+    sender: SyncSender<Job>,
+}
+
+impl ExecutionPool {
+    /// `queue_size` has to be positive, a zero sized queue rejecting every job no idle worker is
+    /// already waiting for
+    pub fn new(workers: usize, queue_size: usize) -> ExecutionPool {
+        assert!(queue_size > 0, "Execution queue cannot be empty");
+        let (sender, receiver) = sync_channel::<Job>(queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for worker_no in 0..workers {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("executor-{}", worker_no))
+                .spawn(move || loop {
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => {
+                            if catch_unwind(AssertUnwindSafe(job)).is_err() {
+                                eprintln!("Execution panicked in worker {}", worker_no);
+                            }
+                        }
+                        Err(_) => break,
+                    }
+                })
+                .expect("Cannot spawn executor thread");
+        }
+        ExecutionPool { sender }
+    }
+
+    /// Queues `job` and waits for its result, failing right away when the queue is full
+    pub async fn run<T, F>(&self, job: F) -> Result<T, PoolError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.submit(job)?.await.map_err(|_| PoolError::Stopped)
+    }
+
+    fn submit<T, F>(&self, job: F) -> Result<oneshot::Receiver<T>, PoolError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            let _ = tx.send(job());
+        });
+        match self.sender.try_send(job) {
+            Ok(()) => Ok(rx),
+            Err(TrySendError::Full(_)) => Err(PoolError::QueueFull),
+            Err(TrySendError::Disconnected(_)) => Err(PoolError::Stopped),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_queue_full() {
+        let pool = ExecutionPool::new(1, 1);
+        let (started_sender, started) = channel();
+        let (release, released) = channel::<()>();
+        let blocked = pool
+            .submit(move || {
+                started_sender.send(()).unwrap();
+                released.recv().unwrap();
+            })
+            .unwrap();
+        started.recv().unwrap();
+
+        // the worker is busy and the queue holds a single job
+        let queued = pool.submit(|| 1).unwrap();
+        assert_eq!(Some(PoolError::QueueFull), pool.submit(|| 2).err());
+
+        release.send(()).unwrap();
+        assert_eq!(Ok(()), blocked.blocking_recv());
+        assert_eq!(Ok(1), queued.blocking_recv());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::manifest::Manifest;
+
+use std::fs;
+use std::io;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// Manifest locations inside a bundle, bundles without one only carry their binary
+pub const MANIFEST_FILES: [&str; 2] = ["manifest.toml", "manifest.json"];
+/// Bundle directory whose files are handed over to the module, read from `/assets` by the guest
+pub const ASSETS_DIR: &str = "assets";
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Whether `bytes` start like a zip bundle rather than a bare module binary
+pub fn is_bundle(bytes: &[u8]) -> bool {
+    bytes.starts_with(ZIP_MAGIC)
+}
+
+/// Contents of a zip bundle: a single `.wasm` at its root, an optional `manifest.toml` or
+/// `manifest.json` and static assets under `assets/`, anything else being ignored
+pub struct Bundle {
+    pub wasm: Vec<u8>,
+    pub manifest: Option<Manifest>,
+    /// Paths relative to the assets directory
+    pub assets: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl Bundle {
+    pub fn from_archive(archive: &mut ZipArchive<impl Read + Seek>) -> Result<Bundle, String> {
+        let mut wasm = None;
+        let mut manifest = None;
+        let mut assets = vec![];
+        for i in 0..archive.len() {
+            let mut entry = archive
+                .by_index(i)
+                .map_err(|err| format!("Cannot read bundle entry {} because {}", i, err))?;
+            if entry.is_dir() {
+                continue;
+            }
+            let path = entry
+                .enclosed_name()
+                .map(Path::to_path_buf)
+                .ok_or_else(|| format!("Bundle entry {:?} escapes the bundle", entry.name()))?;
+            let mut contents = vec![];
+            entry
+                .read_to_end(&mut contents)
+                .map_err(|err| format!("Cannot read {:?} because {}", path, err))?;
+            let at_root = path.components().count() == 1;
+            if at_root && path.extension().is_some_and(|ext| ext == "wasm") {
+                if wasm.replace(contents).is_some() {
+                    return Err("Bundle holds more than one .wasm file".to_string());
+                }
+            } else if at_root && MANIFEST_FILES.iter().any(|file| path == Path::new(file)) {
+                if manifest.is_some() {
+                    return Err("Bundle holds more than one manifest".to_string());
+                }
+                manifest = Some(Manifest::from_file_contents(&path, &contents).map_err(|err| err.to_string())?);
+            } else if let Ok(asset) = path.strip_prefix(ASSETS_DIR) {
+                assets.push((asset.to_path_buf(), contents));
+            }
+        }
+        let wasm = wasm.ok_or_else(|| "Bundle holds no .wasm file".to_string())?;
+        Ok(Bundle {
+            wasm,
+            manifest,
+            assets,
+        })
+    }
+
+    /// Replaces the contents of `dir` with the bundle assets
+    pub fn unpack_assets(&self, dir: &Path) -> io::Result<()> {
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        fs::create_dir_all(dir)?;
+        for (path, contents) in &self.assets {
+            let asset_path = dir.join(path);
+            if let Some(parent) = asset_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(asset_path, contents)?;
+        }
+        Ok(())
+    }
+}
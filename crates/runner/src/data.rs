@@ -1,3 +1,189 @@
+use crate::runner::ExecutionError;
+use crate::schema::Schema;
+
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{Cursor, Read, Write};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Prefix of the environment variables carrying the metadata into the guest
+pub const METADATA_ENV_PREFIX: &str = "WASM_CENTRAL_";
+
+#[derive(Clone)]
 pub struct DataFrame {
-    pub body: Vec<u8>
+    pub body: Vec<u8>,
+    pub metadata: Metadata,
+}
+
+impl DataFrame {
+    pub fn new(body: Vec<u8>) -> DataFrame {
+        DataFrame {
+            body,
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+/// Context of an invocation handed over to the guest along with its input
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    pub sender: String,
+    pub headers: BTreeMap<String, String>,
+    pub content_type: Option<String>,
+    pub schema: Option<Schema>,
+    /// Set by the module handle running the frame
+    pub invocation_id: u64,
+    /// Point in time the caller stops waiting for the result
+    pub deadline: Option<SystemTime>,
+    /// Named handler of the module to call instead of its default one
+    pub entrypoint: Option<String>,
+}
+
+impl Metadata {
+    /// Environment variables exposing the metadata to the guest, values that are not set are left
+    /// out and structured ones are JSON encoded
+    pub fn env(&self) -> Vec<(String, String)> {
+        let mut env = vec![
+            ("SENDER".to_owned(), self.sender.clone()),
+            ("INVOCATION_ID".to_owned(), self.invocation_id.to_string()),
+            ("HEADERS".to_owned(), serde_json::to_string(&self.headers).unwrap()),
+        ];
+        if let Some(content_type) = &self.content_type {
+            env.push(("CONTENT_TYPE".to_owned(), content_type.clone()));
+        }
+        if let Some(entrypoint) = &self.entrypoint {
+            env.push(("ENTRYPOINT".to_owned(), entrypoint.clone()));
+        }
+        if let Some(schema) = &self.schema {
+            env.push(("SCHEMA".to_owned(), serde_json::to_string(schema).unwrap()));
+        }
+        if let Some(deadline) = self.deadline {
+            let deadline_ms = deadline.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+            env.push(("DEADLINE_MS".to_owned(), deadline_ms.to_string()));
+        }
+        env.into_iter()
+            .map(|(name, value)| (format!("{}{}", METADATA_ENV_PREFIX, name), value))
+            .collect()
+    }
+}
+
+/// Outcome of a single call to a module along with what the guest wrote to its stderr
+pub struct Invocation<T = DataFrame> {
+    pub id: u64,
+    pub result: Result<T, ExecutionError>,
+    pub stderr: Vec<u8>,
+}
+
+/// Guest stderr of a single execution, anything past `capacity` bytes is dropped
+#[derive(Clone)]
+pub struct StderrCapture {
+    buffer: Arc<Mutex<Vec<u8>>>,
+    capacity: usize,
+}
+
+impl StderrCapture {
+    pub fn new(capacity: usize) -> StderrCapture {
+        StderrCapture {
+            buffer: Arc::new(Mutex::new(vec![])),
+            capacity,
+        }
+    }
+
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.buffer.lock().unwrap())
+    }
+}
+
+impl Write for StderrCapture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut buffer = self.buffer.lock().unwrap();
+        let available = self.capacity.saturating_sub(buffer.len());
+        buffer.extend_from_slice(&buf[..buf.len().min(available)]);
+        // the guest is not told about truncation, it would only retry
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Guest input made of chunks arriving through a bounded channel, ending once every sender is
+/// dropped. A chunk sent as an error fails the guest read, as does waiting for one past the deadline
+pub struct ChunkReader {
+    receiver: Mutex<Receiver<io::Result<Vec<u8>>>>,
+    current: Cursor<Vec<u8>>,
+    deadline: Option<Instant>,
+}
+
+impl ChunkReader {
+    /// Senders block once `capacity` chunks are waiting to be read
+    pub fn channel(capacity: usize) -> (SyncSender<io::Result<Vec<u8>>>, ChunkReader) {
+        let (sender, receiver) = sync_channel(capacity);
+        let reader = ChunkReader {
+            receiver: Mutex::new(receiver),
+            current: Cursor::new(vec![]),
+            deadline: None,
+        };
+        (sender, reader)
+    }
+
+    /// Fails the reads still waiting for a chunk once `timeout` elapsed from now
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.deadline = Some(Instant::now() + timeout);
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.current.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            let receiver = self.receiver.get_mut().unwrap();
+            let chunk = match self.deadline {
+                Some(deadline) => match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(chunk) => chunk,
+                    Err(RecvTimeoutError::Timeout) => {
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "no input chunk arrived in time"))
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return Ok(0),
+                },
+                None => match receiver.recv() {
+                    Ok(chunk) => chunk,
+                    Err(_) => return Ok(0),
+                },
+            };
+            self.current = Cursor::new(chunk?);
+        }
+    }
+}
+
+/// Guest output handing every write over to a sink as a chunk
+pub struct ChunkWriter {
+    sink: Box<dyn FnMut(Vec<u8>) -> io::Result<()> + Send + Sync>,
+}
+
+impl ChunkWriter {
+    pub fn new(sink: impl FnMut(Vec<u8>) -> io::Result<()> + Send + Sync + 'static) -> ChunkWriter {
+        ChunkWriter {
+            sink: Box::new(sink),
+        }
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            (self.sink)(buf.to_vec())?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
@@ -1,5 +1,14 @@
-use crate::data::DataFrame;
-use crate::runner::{CompilationUnit, Compiler, Executor};
+use crate::bundle::Bundle;
+use crate::data::{ChunkReader, ChunkWriter, DataFrame, Invocation, Metadata, StderrCapture};
+use crate::limits::ExecutionLimits;
+use crate::logs::GuestLog;
+use crate::manifest::{Manifest, RetryPolicy};
+use crate::metrics::ModuleMetrics;
+use crate::routing::{Route, Shadow, SplitMode, TrafficSplit};
+use crate::runner::{CompilationUnit, Compiler, ExecutionError, Executor};
+use crate::schema::{ModuleSchemas, Schema};
+use crate::shadow::ShadowReport;
+use crate::transcode::{self, PayloadFormat};
 use crate::watcher::DirectoryWatcher;
 
 use sha2::{Digest, Sha256};
@@ -7,16 +16,35 @@ use std::collections::HashMap;
 use std::fs;
 use std::io;
 use std::io::{Read, Seek};
-use std::path::PathBuf;
-use std::time::SystemTime;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 use strum_macros::AsRefStr;
 use thiserror::Error;
 use zip::ZipArchive;
 
+/// Bytes of guest stderr kept per invocation
+const STDERR_CAPACITY: usize = 64 * 1024;
+/// Directory of the watched one where bundle assets get unpacked, one directory per module version
+const ASSETS_DIR: &str = ".assets";
+/// Deployed versions kept per module, the active one included
+const MAX_VERSIONS: usize = 5;
+/// Characters of the checksum naming versions of modules whose manifest has no version
+const CHECKSUM_VERSION_LEN: usize = 12;
+
+static NEXT_INVOCATION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Covers the manifest beside a bare binary too, so editing it redeploys the module. Bundles
+/// carry their own and ignore it
 fn get_file_checksum(p: &PathBuf) -> Result<String, io::Error> {
     let mut file = fs::File::open(&p)?;
     let mut hasher = Sha256::new();
     std::io::copy(&mut file, &mut hasher)?;
+    if let Some(sidecar_path) = Manifest::sidecar_path(p).filter(|_| !is_bundle_path(p)) {
+        std::io::copy(&mut fs::File::open(sidecar_path)?, &mut hasher)?;
+    }
     let new_checksum_arr = hasher.finalize();
     Ok(format!("{:x}", new_checksum_arr))
 }
@@ -27,32 +55,259 @@ pub struct Module {
     pub name: String,
     pub status: FunctionStatus,
     pub file_path: PathBuf,
+    /// Manifest version, or else a checksum prefix, set once deployed
+    pub version: String,
+    pub metrics: ModuleMetrics,
+    /// Configuration read from the bundle manifest or from the one beside the binary
+    pub manifest: Option<Manifest>,
     compilation: Option<CompilationUnit>,
 }
 
-pub struct ModuleHandle<'a> {
+/// Cheap to clone snapshot of a deployed module, it keeps running the compilation it was taken
+/// with even if the module gets redeployed or undeployed meanwhile
+#[derive(Clone)]
+pub struct ModuleHandle {
     pub name: String,
+    pub version: String,
     compilation_unit: Option<CompilationUnit>,
-    backreference: &'a FunctionManager,
+    limits: ExecutionLimits,
+    schemas: ModuleSchemas,
+    entrypoints: Vec<String>,
+    retry: RetryPolicy,
+    metrics: ModuleMetrics,
+    executor: Arc<Executor>,
+    guest_log: Option<Arc<GuestLog>>,
 }
 
-impl<'a> ModuleHandle<'a> {
-    pub fn run(&self, frame: &DataFrame) -> Result<DataFrame, String> {
-        match self.backreference
-            .executor
-            .execute(&self.compilation_unit, frame) {
-            Ok(dataframe) => {
-                println!("Successfully executed fn {}", self.name);
-                Ok(dataframe)
+impl ModuleHandle {
+    /// Runs the module on `frame`, checking its body and the output against the module schemas.
+    /// Avro and Protobuf bodies reach the guest as JSON and its output is encoded back the same way
+    pub fn run(&self, frame: &DataFrame) -> Invocation {
+        let (invocation_id, stderr, t_start) = self.start();
+        let result = self.prepare_input(frame, invocation_id).and_then(|input| {
+            let mut attempt = 1;
+            loop {
+                let result = self.executor.execute(&self.compilation_unit, &self.limits, input.clone(), &stderr);
+                match self.next_attempt(&mut attempt, &input, &stderr, result) {
+                    ControlFlow::Continue(backoff) => std::thread::sleep(backoff),
+                    ControlFlow::Break(result) => break result,
+                }
+            }
+        });
+        let result = result.and_then(|output| self.prepare_output(frame, output));
+        self.finish(invocation_id, stderr, t_start, result)
+    }
+
+    /// Runs every frame back to back, each on a fresh instance of the pre-linked module
+    pub fn run_batch(&self, frames: &[DataFrame]) -> Vec<Invocation> {
+        frames.iter().map(|frame| self.run(frame)).collect()
+    }
+
+    /// Streams `input` into the guest stdin and its stdout into `output` while it runs, bodies
+    /// are not checked against the module schemas as they are never whole but the entrypoint is.
+    /// Guest reads waiting on `input` fail once the module timeout elapsed
+    pub fn run_stream(&self, metadata: &Metadata, mut input: ChunkReader, output: ChunkWriter) -> Invocation<()> {
+        if let Some(timeout) = self.limits.timeout {
+            input.set_timeout(timeout);
+        }
+        let (invocation_id, stderr, t_start) = self.start();
+        let metadata = Metadata {
+            invocation_id,
+            ..metadata.clone()
+        };
+        let result = self.check_entrypoint(&metadata).and_then(|()| {
+            self.executor.execute_stream(&self.compilation_unit, &self.limits, &metadata, input, output, &stderr)
+        });
+        self.finish(invocation_id, stderr, t_start, result)
+    }
+
+    pub async fn run_batch_async(&self, frames: &[DataFrame]) -> Vec<Invocation> {
+        let mut invocations = Vec::with_capacity(frames.len());
+        for frame in frames {
+            invocations.push(self.run_async(frame).await);
+        }
+        invocations
+    }
+
+    /// Runs the module on the calling async runtime, only available on managers created through
+    /// `FunctionManager::new_async`. Retries back off on the runtime timer, never blocking it
+    pub async fn run_async(&self, frame: &DataFrame) -> Invocation {
+        let (invocation_id, stderr, t_start) = self.start();
+        let result = match self.prepare_input(frame, invocation_id) {
+            Ok(input) => {
+                let mut attempt = 1;
+                loop {
+                    let result = self
+                        .executor
+                        .execute_async(&self.compilation_unit, &self.limits, input.clone(), &stderr)
+                        .await;
+                    match self.next_attempt(&mut attempt, &input, &stderr, result) {
+                        ControlFlow::Continue(backoff) => tokio::time::sleep(backoff).await,
+                        ControlFlow::Break(result) => break result,
+                    }
+                }
+            }
+            Err(err) => Err(err),
+        };
+        let result = result.and_then(|output| self.prepare_output(frame, output));
+        self.finish(invocation_id, stderr, t_start, result)
+    }
+
+    /// Either the result of the run once an attempt ended with it, or the backoff before the next
+    /// attempt. Only retryable errors get one, as long as attempts are left and the deadline of
+    /// `frame` is not reached by then. The stderr of the failed attempt is dropped
+    fn next_attempt(
+        &self,
+        attempt: &mut u32,
+        frame: &DataFrame,
+        stderr: &StderrCapture,
+        result: Result<DataFrame, ExecutionError>,
+    ) -> ControlFlow<Result<DataFrame, ExecutionError>, Duration> {
+        let backoff = Duration::from_millis(self.retry.backoff_ms);
+        let retry = match &result {
+            Err(err) => err.is_retryable() && *attempt < self.retry.max_attempts,
+            Ok(_) => false,
+        };
+        let in_time = frame
+            .metadata
+            .deadline
+            .is_none_or(|deadline| SystemTime::now() + backoff < deadline);
+        if !retry || !in_time {
+            return ControlFlow::Break(result);
+        }
+        *attempt += 1;
+        stderr.take();
+        ControlFlow::Continue(backoff)
+    }
+
+    /// Rejects calls naming a handler missing from the manifest entrypoints, when it lists any
+    fn check_entrypoint(&self, metadata: &Metadata) -> Result<(), ExecutionError> {
+        match &metadata.entrypoint {
+            Some(entrypoint) if !self.entrypoints.is_empty() && !self.entrypoints.contains(entrypoint) => {
+                Err(ExecutionError::InvalidInput(format!("unknown entrypoint {}", entrypoint)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn input_schema<'a>(&'a self, frame: &'a DataFrame) -> Option<&'a Schema> {
+        frame.metadata.schema.as_ref().or(self.schemas.input.as_ref())
+    }
+
+    /// Frame handed over to the executor, tagged with the invocation running it and with its
+    /// body checked against or decoded with the input schema
+    fn prepare_input(&self, frame: &DataFrame, invocation_id: u64) -> Result<DataFrame, ExecutionError> {
+        self.check_entrypoint(&frame.metadata)?;
+        let mut metadata = Metadata {
+            invocation_id,
+            ..frame.metadata.clone()
+        };
+        let format = PayloadFormat::from_content_type(frame.metadata.content_type.as_deref());
+        let body = match (format, self.input_schema(frame)) {
+            (Some(format), Some(schema)) if format.needs_schema() => {
+                metadata.content_type = Some(PayloadFormat::Json.content_type().to_owned());
+                transcode::to_json(format, schema, &frame.body)
+                    .map_err(|err| ExecutionError::InvalidInput(err.to_string()))?
+            }
+            (Some(format), Some(schema)) => {
+                validate(schema, format, &frame.body).map_err(ExecutionError::InvalidInput)?;
+                frame.body.clone()
+            }
+            (None, Some(_)) => {
+                return Err(ExecutionError::InvalidInput(format!(
+                    "{} bodies cannot be checked against a schema",
+                    frame.metadata.content_type.as_deref().unwrap_or_default()
+                )))
+            }
+            (Some(format), None) if format.needs_schema() => {
+                return Err(ExecutionError::InvalidInput(format!(
+                    "{} bodies need a schema",
+                    format.content_type()
+                )))
+            }
+            _ => frame.body.clone(),
+        };
+        Ok(DataFrame { body, metadata })
+    }
+
+    /// Checks the guest output against the output schema, then encodes it in the declared output
+    /// content type or else in the binary format of the input
+    fn prepare_output(&self, frame: &DataFrame, output: DataFrame) -> Result<DataFrame, ExecutionError> {
+        let input_format = PayloadFormat::from_content_type(frame.metadata.content_type.as_deref());
+        if let Some(schema) = &self.schemas.output {
+            // the guest writes the format it reads, Avro and Protobuf inputs reaching it as JSON
+            let output_format = match input_format {
+                Some(format) if format.needs_schema() => PayloadFormat::Json,
+                Some(format) => format,
+                None => {
+                    return Err(ExecutionError::InvalidOutput(format!(
+                        "outputs of {} inputs cannot be checked against a schema",
+                        frame.metadata.content_type.as_deref().unwrap_or_default()
+                    )))
+                }
+            };
+            validate(schema, output_format, &output.body).map_err(ExecutionError::InvalidOutput)?;
+        }
+        let format = match &self.schemas.output_content_type {
+            Some(content_type) => PayloadFormat::from_content_type(Some(content_type)),
+            None => input_format,
+        };
+        let format = match format {
+            Some(format) if format.needs_schema() => format,
+            _ => return Ok(output),
+        };
+        let schema = self
+            .schemas
+            .output
+            .as_ref()
+            .or_else(|| self.input_schema(frame))
+            .ok_or_else(|| ExecutionError::InvalidOutput(format!("{} bodies need a schema", format.content_type())))?;
+        let body = transcode::from_json(format, schema, &output.body)
+            .map_err(|err| ExecutionError::InvalidOutput(err.to_string()))?;
+        let mut output = DataFrame::new(body);
+        output.metadata.content_type = Some(format.content_type().to_owned());
+        Ok(output)
+    }
+
+    fn start(&self) -> (u64, StderrCapture, Instant) {
+        let invocation_id = NEXT_INVOCATION_ID.fetch_add(1, Ordering::Relaxed);
+        (invocation_id, StderrCapture::new(STDERR_CAPACITY), Instant::now())
+    }
+
+    fn finish<T>(
+        &self,
+        invocation_id: u64,
+        stderr: StderrCapture,
+        t_start: Instant,
+        result: Result<T, ExecutionError>,
+    ) -> Invocation<T> {
+        match &result {
+            Ok(_) => {
+                self.metrics.record_success(t_start.elapsed());
+                println!("Successfully executed fn {} [{}]", self.name, invocation_id);
             },
             Err(err) => {
-                eprintln!("Cannot execute fn named {} because {}", self.name, err);
-                Err(format!("Cannot execute module fn named {}", self.name))
+                self.metrics.record_failure(t_start.elapsed());
+                eprintln!("Cannot execute fn named {} [{}] because {}", self.name, invocation_id, err);
             }
         }
+        let stderr = stderr.take();
+        if let Some(guest_log) = &self.guest_log {
+            guest_log.append(&self.name, invocation_id, &stderr);
+        }
+        Invocation {
+            id: invocation_id,
+            result,
+            stderr,
+        }
     }
 }
 
+fn validate(schema: &Schema, format: PayloadFormat, body: &[u8]) -> Result<(), String> {
+    let value = transcode::to_value(format, body).map_err(|err| err.message)?;
+    schema.validate(&value).map_err(|err| err.to_string())
+}
+
 #[derive(Error, Debug)]
 pub enum FunctionManagerError {
     #[error("Unavailable module {0:?}")]
@@ -60,39 +315,145 @@ pub enum FunctionManagerError {
 
     #[error("Error while compiling {0:?} because {1:?}")]
     CompilationError(String, String),
+
+    #[error("Cannot remove file for module {0:?} because {1:?}")]
+    RemovalError(String, String),
+
+    #[error("Invalid bundle for module {0:?} because {1}")]
+    InvalidBundle(String, String),
+
+    #[error("Invalid manifest for module {0:?} because {1}")]
+    InvalidManifest(String, String),
+
+    #[error("No version of module {0:?} was deployed before the active one")]
+    NoPreviousVersion(String),
+
+    #[error("Invalid traffic split for module {0:?} because {1}")]
+    InvalidTrafficSplit(String, String),
 }
 
 pub struct FunctionManager {
     pub watcher: DirectoryWatcher,
-    module_map: HashMap<String, Module>,
+    /// Active version of every module
+    module_map: RwLock<HashMap<String, Module>>,
+    /// Deployed versions of every module kept for rollbacks, oldest first
+    versions: RwLock<HashMap<String, Vec<Module>>>,
+    traffic_splits: RwLock<HashMap<String, TrafficSplit>>,
+    /// Kept after their split is cleared so they can still be read
+    shadow_reports: RwLock<HashMap<String, ShadowReport>>,
+    default_limits: ExecutionLimits,
+    module_limits: RwLock<HashMap<String, ExecutionLimits>>,
+    module_schemas: RwLock<HashMap<String, ModuleSchemas>>,
     pub compiler: Compiler,
-    pub executor: Executor,
+    pub executor: Arc<Executor>,
+    guest_log: Option<Arc<GuestLog>>,
+    /// Serializes ticks and unloads, executions never take it
+    maintenance: Mutex<()>,
 }
 
 impl FunctionManager {
     pub fn new(path: PathBuf) -> FunctionManager {
-        let (compiler, executor) = crate::runner::new_pair();
+        FunctionManager::with_limits(path, ExecutionLimits::default())
+    }
+
+    pub fn with_limits(path: PathBuf, default_limits: ExecutionLimits) -> FunctionManager {
+        FunctionManager::with_pair(path, default_limits, crate::runner::new_pair())
+    }
+
+    /// Manager whose handles run through `ModuleHandle::run_async`
+    pub fn new_async(path: PathBuf, default_limits: ExecutionLimits) -> FunctionManager {
+        FunctionManager::with_pair(path, default_limits, crate::runner::new_async_pair())
+    }
+
+    fn with_pair(
+        path: PathBuf,
+        default_limits: ExecutionLimits,
+        (compiler, executor): (Compiler, Executor),
+    ) -> FunctionManager {
         FunctionManager {
             watcher: DirectoryWatcher::new(path),
-            module_map: HashMap::new(),
+            module_map: RwLock::new(HashMap::new()),
+            versions: RwLock::new(HashMap::new()),
+            traffic_splits: RwLock::new(HashMap::new()),
+            shadow_reports: RwLock::new(HashMap::new()),
+            default_limits,
+            module_limits: RwLock::new(HashMap::new()),
+            module_schemas: RwLock::new(HashMap::new()),
             compiler,
-            executor,
+            executor: Arc::new(executor),
+            guest_log: None,
+            maintenance: Mutex::new(()),
+        }
+    }
+
+    /// Forwards the stderr of every invocation to per-module files in `dir`, created when missing
+    pub fn with_guest_log(mut self, dir: PathBuf) -> io::Result<FunctionManager> {
+        self.guest_log = Some(Arc::new(GuestLog::new(dir)?));
+        Ok(self)
+    }
+
+    /// Overrides the default limits for a single module, applied to handles taken afterwards
+    pub fn set_module_limits(&self, module_name: &str, limits: ExecutionLimits) {
+        self.module_limits.write().unwrap().insert(module_name.to_owned(), limits);
+    }
+
+    /// Defaults, then limits from the module manifest, then overrides set on the manager
+    pub fn limits_for(&self, module_name: &str) -> ExecutionLimits {
+        let manifest = self.get_fn_by_name(module_name).and_then(|module| module.manifest);
+        self.module_limits_for(module_name, manifest.as_ref())
+    }
+
+    fn module_limits_for(&self, module_name: &str, manifest: Option<&Manifest>) -> ExecutionLimits {
+        let limits = match manifest {
+            Some(manifest) => self.default_limits.merge(&manifest.limits()),
+            None => self.default_limits,
+        };
+        match self.module_limits.read().unwrap().get(module_name) {
+            Some(overrides) => limits.merge(overrides),
+            None => limits,
+        }
+    }
+
+    /// Registers the schemas bodies sent to and returned by a module are checked against, applied
+    /// to handles taken afterwards
+    pub fn set_module_schemas(&self, module_name: &str, schemas: ModuleSchemas) {
+        self.module_schemas.write().unwrap().insert(module_name.to_owned(), schemas);
+    }
+
+    /// Schemas set on the manager, or else the ones from the module manifest
+    pub fn schemas_for(&self, module_name: &str) -> ModuleSchemas {
+        let manifest = self.get_fn_by_name(module_name).and_then(|module| module.manifest);
+        self.module_schemas_for(module_name, manifest.as_ref())
+    }
+
+    fn module_schemas_for(&self, module_name: &str, manifest: Option<&Manifest>) -> ModuleSchemas {
+        match self.module_schemas.read().unwrap().get(module_name) {
+            Some(schemas) => schemas.clone(),
+            None => manifest.map(Manifest::schemas).unwrap_or_default(),
         }
     }
 
     pub fn running_modules_map(&self) -> HashMap<String, Module> {
-        self.module_map.clone()
+        self.module_map.read().unwrap().clone()
     }
 
-    pub fn running_modules(&self) -> Vec<&Module> {
+    /// Deployed versions of a module, oldest first
+    pub fn versions(&self, module_name: &str) -> Vec<Module> {
+        self.versions.read().unwrap().get(module_name).cloned().unwrap_or_default()
+    }
+
+    pub fn running_modules(&self) -> Vec<Module> {
         self.module_map
-            .iter()
-            .map(|(_name, module)| module)
+            .read()
+            .unwrap()
+            .values()
             .filter(|module| module.status.eq(&FunctionStatus::Deployed))
+            .cloned()
             .collect()
     }
 
-    pub fn tick(&mut self) {
+    pub fn tick(&self) {
+        let _maintenance = self.maintenance.lock().unwrap();
         let to_undeploy = self.deleted_functions();
         for item in to_undeploy {
             self.undeploy(&item).unwrap();
@@ -104,15 +465,31 @@ impl FunctionManager {
             let module_name = stem.to_str().unwrap().to_owned();
             let next_status = FunctionStatus::from_string(&file_entry.next_status);
 
-            if let Some(item) = self.module_map.get(&module_name.clone()) {
+            // compared with the latest deployed version rather than the active one, so a file
+            // rolled back from is not deployed again
+            let latest_checksum = self
+                .versions
+                .read()
+                .unwrap()
+                .get(&module_name)
+                .and_then(|versions| versions.last())
+                .map(|item| item.checksum.clone());
+            let known_checksum = latest_checksum.or_else(|| {
+                self.module_map
+                    .read()
+                    .unwrap()
+                    .get(&module_name)
+                    .map(|item| item.checksum.clone())
+            });
+            if let Some(item_checksum) = known_checksum {
                 println!("dropped file {}", file_entry.path.to_str().unwrap().to_string());
                 match get_file_checksum(&file_entry.path) {
                     Ok(file_checksum) => {
-                        if !file_checksum.eq(&item.checksum) {
-                            println!("checksum {} differs from {}: going to reload fn", file_checksum, item.checksum.clone());
+                        if !file_checksum.eq(&item_checksum) {
+                            println!("checksum {} differs from {}: going to reload fn", file_checksum, item_checksum);
                             self.load(&module_name, &next_status, &file_checksum);
                         } else {
-                            println!("same checksum for {} = {}: no reload", module_name, item.checksum.clone());
+                            println!("same checksum for {} = {}: no reload", module_name, item_checksum);
                         }
                     }
                     Err(error) => eprintln!(
@@ -129,9 +506,12 @@ impl FunctionManager {
                             name: module_name.clone(),
                             status: FunctionStatus::Undeployed,
                             file_path: file_entry.path.clone(),
+                            version: String::new(),
+                            metrics: ModuleMetrics::new(),
+                            manifest: None,
                             compilation: None,
                         };
-                        self.module_map.insert(module_name.to_string(), item);
+                        self.module_map.write().unwrap().insert(module_name.to_string(), item);
                         self.load(&module_name, &next_status, &file_checksum);
                     }
                     Err(error) => eprintln!(
@@ -147,7 +527,7 @@ impl FunctionManager {
 
     fn deleted_functions(&self) -> Vec<String> {
         let mut to_undeploy = vec![];
-        for (module_name, module) in self.module_map.iter() {
+        for (module_name, module) in self.module_map.read().unwrap().iter() {
             if !module.file_path.exists() {
                 to_undeploy.push(module_name.clone());
             }
@@ -156,26 +536,184 @@ impl FunctionManager {
     }
 
     pub fn get_handle(&self, module_name: &String) -> Option<ModuleHandle> {
-        if let Some(module) = self.module_map.get(module_name) {
-            let module_status = module.status;
-            if module_status.eq(&FunctionStatus::Deployed) || module_status.eq(&FunctionStatus::Deploy) {
-                if let Some(cu) = module.compilation.clone() {
-                    return Some(ModuleHandle {
-                        name: module_name.clone(),
-                        backreference: self,
-                        compilation_unit: Some(cu),
-                    })
+        self.handle(module_name).ok()
+    }
+
+    /// Like `get_handle` but telling apart unknown modules from modules that cannot run yet.
+    /// `name@version` targets a deployed version other than the active one
+    pub fn handle(&self, module_name: &str) -> Result<ModuleHandle, ExecutionError> {
+        if let Some((name, version)) = module_name.split_once('@') {
+            let versions = self.versions.read().unwrap();
+            let module = versions
+                .get(name)
+                .and_then(|versions| versions.iter().find(|module| module.version == version))
+                .ok_or_else(|| ExecutionError::ModuleNotFound(module_name.to_owned()))?;
+            return self.module_handle(module);
+        }
+        let module_map = self.module_map.read().unwrap();
+        let module = module_map
+            .get(module_name)
+            .ok_or_else(|| ExecutionError::ModuleNotFound(module_name.to_owned()))?;
+        self.module_handle(module)
+    }
+
+    fn module_handle(&self, module: &Module) -> Result<ModuleHandle, ExecutionError> {
+        let module_name = module.name.as_str();
+        let module_status = module.status;
+        if !module_status.eq(&FunctionStatus::Deployed) && !module_status.eq(&FunctionStatus::Deploy) {
+            return Err(ExecutionError::NotDeployed(module_name.to_owned()));
+        }
+        let cu = module
+            .compilation
+            .clone()
+            .ok_or_else(|| ExecutionError::NotDeployed(module_name.to_owned()))?;
+        Ok(ModuleHandle {
+            name: module_name.to_owned(),
+            version: module.version.clone(),
+            compilation_unit: Some(cu),
+            limits: self.module_limits_for(module_name, module.manifest.as_ref()),
+            schemas: self.module_schemas_for(module_name, module.manifest.as_ref()),
+            entrypoints: module
+                .manifest
+                .as_ref()
+                .map(|manifest| manifest.entrypoints.clone())
+                .unwrap_or_default(),
+            retry: module.manifest.as_ref().map(|manifest| manifest.retry).unwrap_or_default(),
+            metrics: module.metrics.clone(),
+            executor: self.executor.clone(),
+            guest_log: self.guest_log.clone(),
+        })
+    }
+
+    /// Sends part of the calls to a module to one of its deployed versions, replacing the
+    /// previous split if any
+    pub fn set_traffic_split(&self, module_name: &str, split: TrafficSplit) -> Result<(), FunctionManagerError> {
+        if !(1..=100).contains(&split.percent) {
+            return Err(FunctionManagerError::InvalidTrafficSplit(
+                module_name.to_owned(),
+                format!("{}% is not between 1% and 100%", split.percent),
+            ));
+        }
+        if !self.versions(module_name).iter().any(|module| module.version == split.version) {
+            return Err(FunctionManagerError::UnavailableModule(format!("{}@{}", module_name, split.version)));
+        }
+        if split.mode == SplitMode::Shadow {
+            let report = ShadowReport::new(split.version.clone());
+            self.shadow_reports.write().unwrap().insert(module_name.to_owned(), report);
+        }
+        self.traffic_splits.write().unwrap().insert(module_name.to_owned(), split);
+        Ok(())
+    }
+
+    /// Outputs of the last candidate shadowing a module compared with those of the active version
+    pub fn shadow_report(&self, module_name: &str) -> Option<ShadowReport> {
+        self.shadow_reports.read().unwrap().get(module_name).cloned()
+    }
+
+    pub fn clear_traffic_split(&self, module_name: &str) -> Option<TrafficSplit> {
+        self.traffic_splits.write().unwrap().remove(module_name)
+    }
+
+    pub fn traffic_split(&self, module_name: &str) -> Option<TrafficSplit> {
+        self.traffic_splits.read().unwrap().get(module_name).cloned()
+    }
+
+    /// Handles a call to `module_name` runs through, following its traffic split unless the
+    /// call names a version
+    pub fn route(&self, module_name: &str) -> Result<Route, ExecutionError> {
+        let handle = self.handle(module_name)?;
+        let split = self
+            .traffic_split(module_name)
+            .filter(|split| split.version != handle.version && split.sample());
+        let split = match split {
+            Some(split) => split,
+            None => return Ok(Route { handle, mirror: None, shadow: None }),
+        };
+        let split_handle = match self.handle(&format!("{}@{}", module_name, split.version)) {
+            Ok(split_handle) => split_handle,
+            Err(err) => {
+                eprintln!("Cannot route fn {} to version {} because {}", module_name, split.version, err);
+                return Ok(Route { handle, mirror: None, shadow: None });
+            }
+        };
+        Ok(match split.mode {
+            SplitMode::Canary => Route {
+                handle: split_handle,
+                mirror: None,
+                shadow: None,
+            },
+            SplitMode::Mirror => Route {
+                handle,
+                mirror: Some(split_handle),
+                shadow: None,
+            },
+            SplitMode::Shadow => Route {
+                handle,
+                mirror: None,
+                shadow: self.shadow_report(module_name).map(|report| Shadow {
+                    handle: split_handle,
+                    report,
+                }),
+            },
+        })
+    }
+
+    /// Reactivates the version deployed before the active one, already compiled so it takes
+    /// over right away. Returns the version now active
+    pub fn rollback(&self, module_name: &str) -> Result<String, FunctionManagerError> {
+        let _maintenance = self.maintenance.lock().unwrap();
+        let active = self
+            .get_fn_by_name(module_name)
+            .filter(|module| module.status.eq(&FunctionStatus::Deployed))
+            .ok_or_else(|| FunctionManagerError::UnavailableModule(module_name.to_owned()))?;
+        let previous = self
+            .versions(module_name)
+            .into_iter()
+            .take_while(|module| module.version != active.version)
+            .last()
+            .ok_or_else(|| FunctionManagerError::NoPreviousVersion(module_name.to_owned()))?;
+        let version = previous.version.clone();
+        self.module_map.write().unwrap().insert(module_name.to_owned(), previous);
+        println!("Rolled back fn {} from version {} to {}", module_name, active.version, version);
+        Ok(version)
+    }
+
+    /// Makes `module` the active version, keeping at most `MAX_VERSIONS` of them along with the
+    /// assets of the ones still kept
+    fn activate(&self, module: Module) {
+        let module_name = module.name.clone();
+        let evicted = {
+            let mut versions = self.versions.write().unwrap();
+            let module_versions = versions.entry(module_name.clone()).or_default();
+            module_versions.retain(|kept| kept.version != module.version);
+            module_versions.push(module.clone());
+            let evicted = module_versions.len().saturating_sub(MAX_VERSIONS);
+            module_versions.drain(..evicted).collect::<Vec<Module>>()
+        };
+        let mut traffic_splits = self.traffic_splits.write().unwrap();
+        if traffic_splits
+            .get(&module_name)
+            .is_some_and(|split| evicted.iter().any(|module| module.version == split.version))
+        {
+            traffic_splits.remove(&module_name);
+        }
+        drop(traffic_splits);
+        for evicted in evicted {
+            let assets_dir = self.assets_dir(&module_name, &evicted.version);
+            if assets_dir.exists() {
+                if let Err(err) = fs::remove_dir_all(&assets_dir) {
+                    eprintln!("Cannot remove assets of {}@{} because {:?}", module_name, evicted.version, err);
                 }
             }
         }
-        None
+        self.module_map.write().unwrap().insert(module_name, module);
     }
 
-    fn get_fn_by_name(&mut self, name: &str) -> Option<Module> {
-        return self.module_map.get(name).cloned();
+    fn get_fn_by_name(&self, name: &str) -> Option<Module> {
+        return self.module_map.read().unwrap().get(name).cloned();
     }
 
-    pub fn load(&mut self, module_name: &String, new_status: &FunctionStatus, file_checksum: &String) {
+    pub fn load(&self, module_name: &String, new_status: &FunctionStatus, file_checksum: &String) {
         println!("Loading fn {} with status {}", module_name, new_status.as_string());
         let t_now = SystemTime::now();
         if let Some(module) = self.get_fn_by_name(&module_name.clone()) {
@@ -218,39 +756,130 @@ impl FunctionManager {
         );
     }
 
-    fn deploy(&mut self, module_name: &str, checksum: String) -> Result<(), FunctionManagerError> {
-        let module_map = self.running_modules_map();
-        if let Some(module) = module_map.get(&module_name.to_owned()) {
-            if let Ok(mut file) = fs::File::open(module.file_path.clone()) {
-                let compilation_unit_result = self.compiler.compile(&mut file);
-                if compilation_unit_result.is_err() {
-                    return Err(FunctionManagerError::CompilationError(
-                        module_name.to_owned(),
-                        format!(
-                            "couldn't JIT compile WASM: {:?}",
-                            compilation_unit_result.err().unwrap()
-                        ),
-                    ));
-                }
-                let compilation = Some(compilation_unit_result.unwrap());
-                self.module_map.insert(module_name.to_owned(), Module { status: FunctionStatus::Deployed, compilation, checksum, ..module.clone() });
-                Ok(())
+    /// Compiles outside of the module map lock, so executions keep running the previous
+    /// compilation until the new one is swapped in
+    fn deploy(&self, module_name: &str, checksum: String) -> Result<(), FunctionManagerError> {
+        let retained = self
+            .versions(module_name)
+            .into_iter()
+            .find(|module| module.checksum == checksum);
+        if let Some(retained) = retained {
+            println!("Reactivating fn {} version {} without compiling", module_name, retained.version);
+            self.activate(retained);
+            return Ok(());
+        }
+        if let Some(module) = self.get_fn_by_name(module_name) {
+            let (compilation_unit_result, manifest, bundle) = if is_bundle_path(&module.file_path) {
+                let mut bundle = open_zip(module.file_path.clone())
+                    .and_then(|mut archive| Bundle::from_archive(&mut archive))
+                    .map_err(|err| FunctionManagerError::InvalidBundle(module_name.to_owned(), err))?;
+                let compilation_unit_result = self.compiler.compile(&mut bundle.wasm.as_slice());
+                (compilation_unit_result, bundle.manifest.take(), Some(bundle))
+            } else if let Ok(mut file) = fs::File::open(module.file_path.clone()) {
+                let manifest = Manifest::read_sidecar(&module.file_path)
+                    .map_err(|err| FunctionManagerError::InvalidManifest(module_name.to_owned(), err.to_string()))?;
+                (self.compiler.compile(&mut file), manifest, None)
             } else {
-                Err(FunctionManagerError::UnavailableModule(
+                return Err(FunctionManagerError::UnavailableModule(module_name.to_owned()));
+            };
+            if let Some(manifest) = &manifest {
+                manifest
+                    .validate(module_name)
+                    .map_err(|err| FunctionManagerError::InvalidManifest(module_name.to_owned(), err.to_string()))?;
+            }
+            if compilation_unit_result.is_err() {
+                return Err(FunctionManagerError::CompilationError(
                     module_name.to_owned(),
-                ))
+                    format!(
+                        "couldn't JIT compile WASM: {:?}",
+                        compilation_unit_result.err().unwrap()
+                    ),
+                ));
             }
+            let mut compilation_unit = compilation_unit_result.unwrap();
+            let version = manifest
+                .as_ref()
+                .and_then(|manifest| manifest.version.clone())
+                .unwrap_or_else(|| checksum.chars().take(CHECKSUM_VERSION_LEN).collect());
+            if let Some(manifest) = &manifest {
+                compilation_unit = compilation_unit.with_env(manifest.env());
+            }
+            let grants_assets = manifest.as_ref().is_none_or(|manifest| manifest.allows("assets"));
+            if let Some(bundle) = bundle.filter(|bundle| grants_assets && !bundle.assets.is_empty()) {
+                let assets_dir = self.assets_dir(module_name, &version);
+                bundle.unpack_assets(&assets_dir).map_err(|err| {
+                    FunctionManagerError::InvalidBundle(
+                        module_name.to_owned(),
+                        format!("cannot unpack assets because {}", err),
+                    )
+                })?;
+                compilation_unit = compilation_unit.with_assets(assets_dir);
+            }
+            let compilation = Some(compilation_unit);
+            // a new binary starts with fresh counters, handles still running the previous
+            // one keep reporting into the old ones
+            let metrics = ModuleMetrics::new();
+            self.activate(Module { status: FunctionStatus::Deployed, compilation, checksum, version, metrics, manifest, ..module });
+            Ok(())
         } else {
             Err(FunctionManagerError::UnavailableModule(module_name.to_owned()))
         }
     }
 
-    fn undeploy(&mut self, module_name: &String) -> Result<FunctionStatus, FunctionManagerError> {
-        if let Some(module) = self.module_map.get(&module_name.clone()) {
+    fn module_assets_dir(&self, module_name: &str) -> PathBuf {
+        self.watcher.dir.join(ASSETS_DIR).join(module_name)
+    }
+
+    fn assets_dir(&self, module_name: &str, version: &str) -> PathBuf {
+        self.module_assets_dir(module_name).join(version)
+    }
+
+    /// Undeploys a module and removes its file and sidecar manifests from the watched directory,
+    /// so it is not picked up again by the next tick.
+    pub fn unload(&self, module_name: &str) -> Result<PathBuf, FunctionManagerError> {
+        let _maintenance = self.maintenance.lock().unwrap();
+        let removed = self.module_map.write().unwrap().remove(module_name);
+        if let Some(module) = removed {
+            let module_path = module.file_path.clone();
+            // sidecars first, a stale one would apply to the next upload under the same name
+            let removal = Manifest::sidecar_paths(&module_path)
+                .into_iter()
+                .chain(module_path.exists().then(|| module_path.clone()))
+                .try_for_each(fs::remove_file);
+            if let Err(err) = removal {
+                self.module_map.write().unwrap().insert(module_name.to_owned(), module);
+                return Err(FunctionManagerError::RemovalError(
+                    module_name.to_owned(),
+                    format!("{:?}", err),
+                ));
+            }
+            self.watcher.remove_states(module_name);
+            self.versions.write().unwrap().remove(module_name);
+            self.traffic_splits.write().unwrap().remove(module_name);
+            self.shadow_reports.write().unwrap().remove(module_name);
+            let assets_dir = self.module_assets_dir(module_name);
+            if assets_dir.exists() {
+                if let Err(err) = fs::remove_dir_all(&assets_dir) {
+                    eprintln!("Cannot remove assets of {} because {:?}", module_name, err);
+                }
+            }
+            println!("Unloaded fn {} from {}", module_name, module_path.display());
+            Ok(module_path)
+        } else {
+            Err(FunctionManagerError::UnavailableModule(module_name.to_owned()))
+        }
+    }
+
+    fn undeploy(&self, module_name: &String) -> Result<FunctionStatus, FunctionManagerError> {
+        let mut module_map = self.module_map.write().unwrap();
+        if let Some(module) = module_map.get(&module_name.clone()) {
             let module_path = module.file_path.clone();
             if !module_path.exists() {
                 let _ = fs::remove_file(module_path);
-                self.module_map.remove(module_name).unwrap();
+                module_map.remove(module_name).unwrap();
+                self.versions.write().unwrap().remove(module_name);
+                self.traffic_splits.write().unwrap().remove(module_name);
+                self.shadow_reports.write().unwrap().remove(module_name);
             }
             Ok(FunctionStatus::Undeployed)
         } else {
@@ -259,6 +888,10 @@ impl FunctionManager {
     }
 }
 
+fn is_bundle_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "zip")
+}
+
 fn open_zip(path: PathBuf) -> Result<ZipArchive<impl Read + Seek>, String> {
     if let Ok(file) = fs::File::open(path) {
         if let Ok(archive) = ZipArchive::new(file) {
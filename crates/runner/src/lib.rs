@@ -1,6 +1,15 @@
 extern crate core;
 
+pub mod bundle;
 pub mod data;
 pub mod functions;
+pub mod limits;
+pub mod logs;
+pub mod manifest;
+pub mod metrics;
+pub mod routing;
 pub mod runner;
+pub mod schema;
+pub mod shadow;
+pub mod transcode;
 pub mod watcher;
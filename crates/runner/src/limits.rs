@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+/// Budgets applied to a single guest execution, `None` meaning unbounded
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ExecutionLimits {
+    /// Wasmtime fuel units the guest can consume
+    pub fuel: Option<u64>,
+    /// Wall-clock time the guest can run for
+    pub timeout: Option<Duration>,
+    /// Bytes a single linear memory can grow to
+    pub max_memory_bytes: Option<usize>,
+    /// Elements a single table can grow to
+    pub max_table_elements: Option<u32>,
+    /// Instances a single execution can create
+    pub max_instances: Option<usize>,
+}
+
+impl ExecutionLimits {
+    /// Limits set on `overrides` win over the ones set on `self`
+    pub fn merge(&self, overrides: &ExecutionLimits) -> ExecutionLimits {
+        ExecutionLimits {
+            fuel: overrides.fuel.or(self.fuel),
+            timeout: overrides.timeout.or(self.timeout),
+            max_memory_bytes: overrides.max_memory_bytes.or(self.max_memory_bytes),
+            max_table_elements: overrides.max_table_elements.or(self.max_table_elements),
+            max_instances: overrides.max_instances.or(self.max_instances),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Appends the stderr of every invocation to `<dir>/<module>.log`, one line per guest line
+/// prefixed by the invocation id
+pub struct GuestLog {
+    dir: PathBuf,
+    /// Set while appends fail, so a broken log is reported once rather than on every invocation
+    failing: AtomicBool,
+}
+
+impl GuestLog {
+    /// Creates `dir` when missing
+    pub fn new(dir: PathBuf) -> io::Result<GuestLog> {
+        fs::create_dir_all(&dir)?;
+        Ok(GuestLog {
+            dir,
+            failing: AtomicBool::new(false),
+        })
+    }
+
+    pub fn append(&self, module_name: &str, invocation_id: u64, stderr: &[u8]) {
+        if stderr.is_empty() {
+            return;
+        }
+        let mut lines = vec![];
+        for line in String::from_utf8_lossy(stderr).lines() {
+            lines.extend_from_slice(format!("[{}] {}\n", invocation_id, line).as_bytes());
+        }
+        let log_path = self.dir.join(format!("{}.log", module_name));
+        let written = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .and_then(|mut file| file.write_all(&lines));
+        match written {
+            Ok(()) => self.failing.store(false, Ordering::Relaxed),
+            Err(err) if !self.failing.swap(true, Ordering::Relaxed) => {
+                eprintln!("Cannot write guest log at {:?} because {:?}", log_path, err);
+            }
+            Err(_) => {}
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use crate::data::METADATA_ENV_PREFIX;
+use crate::limits::ExecutionLimits;
+use crate::schema::{ModuleSchemas, Schema};
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Capabilities the host can withhold from modules, stdio, clocks and randomness being granted to
+/// every one of them
+pub const HOST_CAPABILITIES: [&str; 2] = ["env", "assets"];
+/// Suffixes of the manifest files read beside a bare `name.wasm`, e.g. `name.manifest.toml`
+pub const SIDECAR_SUFFIXES: [&str; 2] = ["manifest.toml", "manifest.json"];
+
+/// Execution limits declared by a module, operator overrides still win over them
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ManifestLimits {
+    pub fuel: Option<u64>,
+    pub timeout_ms: Option<u64>,
+    pub max_memory_bytes: Option<usize>,
+    pub max_table_elements: Option<u32>,
+    pub max_instances: Option<usize>,
+}
+
+impl ManifestLimits {
+    pub fn to_limits(&self) -> ExecutionLimits {
+        ExecutionLimits {
+            fuel: self.fuel,
+            timeout: self.timeout_ms.map(Duration::from_millis),
+            max_memory_bytes: self.max_memory_bytes,
+            max_table_elements: self.max_table_elements,
+            max_instances: self.max_instances,
+        }
+    }
+}
+
+/// How many times a run failing with a retryable error is attempted
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// Attempts including the first one
+    pub max_attempts: u32,
+    /// Pause between two attempts
+    pub backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            backoff_ms: 0,
+        }
+    }
+}
+
+/// Description of a module shipped along with its binary, every field being optional
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Manifest {
+    /// Has to match the module name when set
+    pub name: Option<String>,
+    pub display_name: Option<String>,
+    /// Names the deployed version, `name@version` targeting it. A checksum prefix is used when
+    /// not set
+    pub version: Option<String>,
+    pub description: Option<String>,
+    pub owner: Option<String>,
+    /// Named handlers the module can be called through, any name being accepted when empty
+    pub entrypoints: Vec<String>,
+    pub limits: ManifestLimits,
+    /// Shorthand for `limits.timeout_ms`, winning over it when both are set
+    pub timeout_ms: Option<u64>,
+    pub retry: RetryPolicy,
+    /// Environment variables set for every run, next to the metadata ones
+    pub env: BTreeMap<String, String>,
+    pub input_schema: Option<Schema>,
+    pub output_schema: Option<Schema>,
+    pub output_content_type: Option<String>,
+    /// Host capabilities the module is granted, see `HOST_CAPABILITIES`, every one when empty
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum ManifestError {
+    #[error("Cannot parse manifest because {0}")]
+    Parse(String),
+
+    #[error("Manifest describes {0:?} instead of {1:?}")]
+    NameMismatch(String, String),
+
+    #[error("Unsupported capability {0:?}")]
+    UnsupportedCapability(String),
+
+    #[error("Invalid entrypoint {0:?}")]
+    InvalidEntrypoint(String),
+
+    #[error("Schema {0} has no records")]
+    EmptySchema(&'static str),
+
+    #[error("Invalid environment variable {0:?}")]
+    InvalidEnv(String),
+
+    #[error("Capability {0:?} is needed but not allowed")]
+    MissingCapability(&'static str),
+
+    #[error("Retry policy needs at least one attempt")]
+    InvalidRetry,
+
+    #[error("Invalid version {0:?}")]
+    InvalidVersion(String),
+}
+
+impl Manifest {
+    pub fn from_json(bytes: &[u8]) -> Result<Manifest, ManifestError> {
+        serde_json::from_slice(bytes).map_err(|err| ManifestError::Parse(err.to_string()))
+    }
+
+    pub fn from_toml(bytes: &[u8]) -> Result<Manifest, ManifestError> {
+        toml::from_slice(bytes).map_err(|err| ManifestError::Parse(err.to_string()))
+    }
+
+    /// Parses `bytes` as TOML or JSON depending on the extension of `path`
+    pub fn from_file_contents(path: &Path, bytes: &[u8]) -> Result<Manifest, ManifestError> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Manifest::from_toml(bytes),
+            Some("json") => Manifest::from_json(bytes),
+            _ => Err(ManifestError::Parse(format!("unknown manifest format for {:?}", path))),
+        }
+    }
+
+    /// First sidecar manifest found beside the module binary at `module_path`
+    pub fn sidecar_path(module_path: &Path) -> Option<PathBuf> {
+        Manifest::sidecar_paths(module_path).into_iter().next()
+    }
+
+    /// Every sidecar manifest found beside the module binary at `module_path`, in lookup order
+    pub fn sidecar_paths(module_path: &Path) -> Vec<PathBuf> {
+        let stem = match module_path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(stem) => stem,
+            None => return vec![],
+        };
+        SIDECAR_SUFFIXES
+            .iter()
+            .map(|suffix| module_path.with_file_name(format!("{}.{}", stem, suffix)))
+            .filter(|path| path.exists())
+            .collect()
+    }
+
+    pub fn read_sidecar(module_path: &Path) -> Result<Option<Manifest>, ManifestError> {
+        match Manifest::sidecar_path(module_path) {
+            Some(path) => {
+                let bytes = fs::read(&path).map_err(|err| ManifestError::Parse(err.to_string()))?;
+                Manifest::from_file_contents(&path, &bytes).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn validate(&self, module_name: &str) -> Result<(), ManifestError> {
+        if let Some(name) = &self.name {
+            if name != module_name {
+                return Err(ManifestError::NameMismatch(name.clone(), module_name.to_owned()));
+            }
+        }
+        if let Some(version) = &self.version {
+            let valid = !version.is_empty()
+                && version != "."
+                && version != ".."
+                && !version.contains(|c: char| c == '@' || c == '/' || c == '\\' || c.is_whitespace());
+            if !valid {
+                return Err(ManifestError::InvalidVersion(version.clone()));
+            }
+        }
+        if let Some(capability) = self
+            .capabilities
+            .iter()
+            .find(|capability| !HOST_CAPABILITIES.contains(&capability.as_str()))
+        {
+            return Err(ManifestError::UnsupportedCapability(capability.clone()));
+        }
+        if let Some(entrypoint) = self
+            .entrypoints
+            .iter()
+            .find(|entrypoint| entrypoint.is_empty() || entrypoint.contains(char::is_whitespace))
+        {
+            return Err(ManifestError::InvalidEntrypoint(entrypoint.clone()));
+        }
+        for (kind, schema) in [("input", &self.input_schema), ("output", &self.output_schema)] {
+            if schema.as_ref().is_some_and(|schema| schema.records.is_empty()) {
+                return Err(ManifestError::EmptySchema(kind));
+            }
+        }
+        if let Some(name) = self.env.keys().find(|name| {
+            name.is_empty() || name.contains('=') || name.starts_with(METADATA_ENV_PREFIX)
+        }) {
+            return Err(ManifestError::InvalidEnv(name.clone()));
+        }
+        if !self.env.is_empty() && !self.allows("env") {
+            return Err(ManifestError::MissingCapability("env"));
+        }
+        if self.retry.max_attempts == 0 {
+            return Err(ManifestError::InvalidRetry);
+        }
+        Ok(())
+    }
+
+    /// Whether the module is granted `capability`
+    pub fn allows(&self, capability: &str) -> bool {
+        self.capabilities.is_empty() || self.capabilities.iter().any(|allowed| allowed == capability)
+    }
+
+    /// Limits declared by the module, to be merged between the defaults and the overrides
+    pub fn limits(&self) -> ExecutionLimits {
+        ExecutionLimits {
+            timeout: self.timeout_ms.map(Duration::from_millis).or(self.limits.to_limits().timeout),
+            ..self.limits.to_limits()
+        }
+    }
+
+    pub fn env(&self) -> Vec<(String, String)> {
+        self.env.iter().map(|(name, value)| (name.clone(), value.clone())).collect()
+    }
+
+    pub fn schemas(&self) -> ModuleSchemas {
+        ModuleSchemas {
+            input: self.input_schema.clone(),
+            output: self.output_schema.clone(),
+            output_content_type: self.output_content_type.clone(),
+        }
+    }
+}
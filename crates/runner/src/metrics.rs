@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Window used to compute the failure rate per minute
+const FAILURE_WINDOW: Duration = Duration::from_secs(60);
+/// Amount of latency samples kept to compute percentiles
+const LATENCY_SAMPLES: usize = 1024;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LatencyPercentiles {
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    pub successes: u64,
+    pub failures: u64,
+    pub total_messages: u64,
+    pub fail_rate_per_minute: f64,
+    pub latency: LatencyPercentiles,
+}
+
+#[derive(Default)]
+struct MetricsState {
+    successes: u64,
+    failures: u64,
+    failure_times: VecDeque<Instant>,
+    latencies: VecDeque<Duration>,
+}
+
+impl MetricsState {
+    fn record_latency(&mut self, elapsed: Duration) {
+        if self.latencies.len() == LATENCY_SAMPLES {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(elapsed);
+    }
+
+    fn expire_failures(&mut self, now: Instant) {
+        while let Some(failure_time) = self.failure_times.front() {
+            if now.duration_since(*failure_time) > FAILURE_WINDOW {
+                self.failure_times.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+/// Execution counters of a deployed module, shared between the module and its handles
+#[derive(Clone, Default)]
+pub struct ModuleMetrics {
+    state: Arc<Mutex<MetricsState>>,
+}
+
+impl ModuleMetrics {
+    pub fn new() -> ModuleMetrics {
+        ModuleMetrics::default()
+    }
+
+    pub fn record_success(&self, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.successes += 1;
+        state.record_latency(elapsed);
+    }
+
+    pub fn record_failure(&self, elapsed: Duration) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.failures += 1;
+        state.record_latency(elapsed);
+        state.expire_failures(now);
+        state.failure_times.push_back(now);
+    }
+
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = MetricsState::default();
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let mut state = self.state.lock().unwrap();
+        state.expire_failures(Instant::now());
+        let mut latencies = state.latencies.iter().cloned().collect::<Vec<Duration>>();
+        latencies.sort();
+        MetricsSnapshot {
+            successes: state.successes,
+            failures: state.failures,
+            total_messages: state.successes + state.failures,
+            fail_rate_per_minute: state.failure_times.len() as f64 * 60.0
+                / FAILURE_WINDOW.as_secs_f64(),
+            latency: LatencyPercentiles {
+                p50_ms: percentile(&latencies, 0.50),
+                p90_ms: percentile(&latencies, 0.90),
+                p99_ms: percentile(&latencies, 0.99),
+            },
+        }
+    }
+}
+
+/// Nearest-rank percentile over already sorted samples, in milliseconds
+fn percentile(sorted: &[Duration], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    let index = rank.clamp(1, sorted.len()) - 1;
+    sorted[index].as_secs_f64() * 1000.0
+}
@@ -0,0 +1,76 @@
+use crate::data::DataFrame;
+use crate::functions::ModuleHandle;
+use crate::shadow::ShadowReport;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// What the calls picked by a traffic split do with the split version
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitMode {
+    /// Picked calls run the split version instead of the active one
+    Canary,
+    /// Picked calls run the active version, then the split one on the same input with its
+    /// output discarded
+    Mirror,
+    /// Like `Mirror`, the split version output being compared with the active one in the
+    /// module shadow report
+    Shadow,
+}
+
+/// Share of the calls to a module sent to one of its deployed versions besides the active one
+#[derive(Clone, Debug)]
+pub struct TrafficSplit {
+    pub version: String,
+    /// From 1 to 100
+    pub percent: u8,
+    pub mode: SplitMode,
+    calls: Arc<AtomicU64>,
+}
+
+impl TrafficSplit {
+    pub fn new(version: String, percent: u8, mode: SplitMode) -> TrafficSplit {
+        TrafficSplit {
+            version,
+            percent,
+            mode,
+            calls: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Whether the next call goes to the split version, exactly `percent` calls out of every
+    /// hundred being picked and spread evenly among them
+    pub fn sample(&self) -> bool {
+        let call = self.calls.fetch_add(1, Ordering::Relaxed) % 100;
+        let percent = self.percent as u64;
+        (call + 1) * percent / 100 > call * percent / 100
+    }
+}
+
+/// Handles a single call runs through once the traffic split of its module is applied
+pub struct Route {
+    pub handle: ModuleHandle,
+    /// Run on the same input as `handle`, its result only counting in its version metrics
+    pub mirror: Option<ModuleHandle>,
+    pub shadow: Option<Shadow>,
+}
+
+/// Candidate version run on the same input as the active one once it succeeded
+#[derive(Clone)]
+pub struct Shadow {
+    pub handle: ModuleHandle,
+    pub report: ShadowReport,
+}
+
+impl Shadow {
+    /// Runs the candidate on `frame`, recording how its output differs from `active_output`
+    pub fn compare(&self, frame: &DataFrame, invocation_id: u64, active_output: &DataFrame) {
+        let invocation = self.handle.run(frame);
+        self.report.record(invocation_id, active_output, &invocation.result);
+    }
+
+    pub async fn compare_async(&self, frame: &DataFrame, invocation_id: u64, active_output: &DataFrame) {
+        let invocation = self.handle.run_async(frame).await;
+        self.report.record(invocation_id, active_output, &invocation.result);
+    }
+}
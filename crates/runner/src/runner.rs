@@ -1,23 +1,97 @@
+use std::any::Any;
 use std::borrow::{Borrow, BorrowMut};
 use std::collections::VecDeque;
 use std::fmt::format;
 use std::fs;
-use crate::data::DataFrame;
+use crate::data::{DataFrame, Metadata, StderrCapture};
+use crate::limits::ExecutionLimits;
 
 use fork::Fork;
-use std::io::{Read, Seek, SeekFrom, stderr, stdout};
+use std::io::{Cursor, Read, Seek, SeekFrom, stderr, stdout};
 use std::ops::Deref;
-use std::sync::{Arc, RwLock};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock, Weak};
+use std::thread;
+use std::time::Duration;
 use wasi_cap_std_sync::file::File;
 use std::io::Write;
 use std::rc::Rc;
-use wasi_cap_std_sync::WasiCtxBuilder;
-use wasmtime::{Module, Store, Engine, Instance, AsContextMut, Linker};
+use wasi_cap_std_sync::{ambient_authority, Dir, WasiCtxBuilder};
+use wasi_common::pipe::{ReadPipe, WritePipe};
+use wasi_common::WasiFile;
+use thiserror::Error;
+use wasmtime::{Module, Store, Engine, Instance, InstancePre, AsContext, AsContextMut, Linker, ResourceLimiter, Trap, TrapCode, TypedFunc};
 use wasmtime_wasi::WasiCtx;
 
+/// Granularity of the epoch used to interrupt guests running over their timeout
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+/// Epoch deadline for executions without a timeout, far enough to never be reached
+const NO_DEADLINE: u64 = u64::MAX / 2;
+/// Fuel an async execution burns before yielding back to the async runtime
+const YIELD_FUEL: u64 = 100_000;
+
+/// Data owned by every store: the guest WASI context and its resource limiter
+pub struct StoreState {
+    wasi: WasiCtx,
+    limiter: GuestLimiter,
+}
+
+impl StoreState {
+    fn new(wasi: WasiCtx, limits: ExecutionLimits) -> StoreState {
+        StoreState {
+            wasi,
+            limiter: GuestLimiter {
+                limits,
+                memory_denied: false,
+            },
+        }
+    }
+}
+
+/// Enforces the memory, table and instance caps of an execution, remembering whether the last
+/// memory growth had to be denied so the resulting trap can be reported as such
+struct GuestLimiter {
+    limits: ExecutionLimits,
+    memory_denied: bool,
+}
+
+impl ResourceLimiter for GuestLimiter {
+    fn memory_growing(&mut self, _current: usize, desired: usize, _maximum: Option<usize>) -> bool {
+        let allowed = self.limits.max_memory_bytes.map(|max| desired <= max).unwrap_or(true);
+        self.memory_denied = !allowed;
+        allowed
+    }
+
+    fn table_growing(&mut self, _current: u32, desired: u32, _maximum: Option<u32>) -> bool {
+        self.limits.max_table_elements.map(|max| desired <= max).unwrap_or(true)
+    }
+
+    fn instances(&self) -> usize {
+        self.limits.max_instances.unwrap_or(wasmtime::DEFAULT_INSTANCE_LIMIT)
+    }
+}
+
 #[derive(Clone)]
 pub struct CompilationUnit {
     module: Module,
+    instance_pre: InstancePre<StoreState>,
+    /// Host directory preopened as `/assets` for the guest
+    assets: Option<PathBuf>,
+    /// Environment variables of the module, set along with the metadata ones
+    env: Vec<(String, String)>,
+}
+
+impl CompilationUnit {
+    pub fn with_assets(self, assets: PathBuf) -> CompilationUnit {
+        CompilationUnit {
+            assets: Some(assets),
+            ..self
+        }
+    }
+
+    pub fn with_env(self, env: Vec<(String, String)>) -> CompilationUnit {
+        CompilationUnit { env, ..self }
+    }
 }
 
 pub struct Compiler {
@@ -25,6 +99,31 @@ pub struct Compiler {
 }
 
 pub fn new_pair() -> (Compiler, Executor) {
+    let engine_arc = Arc::new(wasmtime::Engine::new(&engine_config()).expect("WASM engine"));
+    start_epoch_ticker(Arc::downgrade(&engine_arc));
+    let compiler = Compiler::new(engine_arc.clone());
+    let executor = Executor::new(engine_arc);
+    (compiler, executor)
+}
+
+/// Pair whose executor only runs guests through `Executor::execute_async`, yielding to the
+/// async runtime every `YIELD_FUEL` units of fuel
+pub fn new_async_pair() -> (Compiler, Executor) {
+    let mut config = engine_config();
+    config.async_support(true);
+
+    let engine_arc = Arc::new(wasmtime::Engine::new(&config).expect("WASM engine"));
+    start_epoch_ticker(Arc::downgrade(&engine_arc));
+    let compiler = Compiler::new(engine_arc.clone());
+    let executor = Executor {
+        engine: engine_arc,
+        async_support: true,
+    };
+    (compiler, executor)
+}
+
+/// Configuration of the engines running guests, metering fuel and interruptible through epochs
+pub fn engine_config() -> wasmtime::Config {
     let mut config = wasmtime::Config::new();
     config.cache_config_load_default().expect("working cache directory");
 
@@ -33,11 +132,132 @@ pub fn new_pair() -> (Compiler, Executor) {
     config.wasm_threads(false);
     config.wasm_bulk_memory(false);
 
+    config.consume_fuel(true);
+    config.epoch_interruption(true);
+    config
+}
 
-    let engine_arc = Arc::new(wasmtime::Engine::new(&config).expect("WASM engine"));
-    let compiler = Compiler::new(engine_arc.clone());
-    let executor = Executor::new(engine_arc);
-    (compiler, executor)
+/// Advances the engine epoch every `EPOCH_TICK` until the engine is dropped
+fn start_epoch_ticker(engine: Weak<Engine>) {
+    thread::spawn(move || {
+        while let Some(engine) = engine.upgrade() {
+            engine.increment_epoch();
+            drop(engine);
+            thread::sleep(EPOCH_TICK);
+        }
+    });
+}
+
+fn epoch_deadline(limits: &ExecutionLimits) -> u64 {
+    match limits.timeout {
+        Some(timeout) => (timeout.as_millis() / EPOCH_TICK.as_millis()) as u64 + 1,
+        None => NO_DEADLINE,
+    }
+}
+
+/// Tells apart the guest failures caused by a tripped limit from the rest
+fn classify_failure(err: anyhow::Error, store: &Store<StoreState>, limits: &ExecutionLimits) -> ExecutionError {
+    let memory_denied = store.data().limiter.memory_denied;
+    let memory_limit_exceeded = || ExecutionError::MemoryLimitExceeded(limits.max_memory_bytes.unwrap_or_default());
+    let trap = match err.downcast::<Trap>() {
+        Ok(trap) => trap,
+        // memories too large for the limit fail the instantiation without a trap
+        Err(_) if memory_denied => return memory_limit_exceeded(),
+        Err(err) => return ExecutionError::Failure(err),
+    };
+    // clean exits never get here, see `ignore_clean_exit`
+    if let Some(status) = trap.i32_exit_status() {
+        return ExecutionError::NonZeroExit(status);
+    }
+    if let Some(timeout) = limits.timeout {
+        if trap.trap_code() == Some(TrapCode::Interrupt) {
+            return ExecutionError::Timeout(timeout);
+        }
+    }
+    if let Some(fuel) = limits.fuel {
+        if store.fuel_consumed().map(|consumed| consumed >= fuel).unwrap_or(false) {
+            return ExecutionError::FuelExhausted(fuel);
+        }
+    }
+    // a guest failing to allocate aborts or reads past its memory right after the denied growth,
+    // a later growth that went through meaning the guest got over it
+    let allocation_failure = matches!(
+        trap.trap_code(),
+        Some(TrapCode::UnreachableCodeReached) | Some(TrapCode::MemoryOutOfBounds)
+    );
+    if memory_denied && allocation_failure {
+        return memory_limit_exceeded();
+    }
+    ExecutionError::Trap {
+        trap_code: trap.trap_code(),
+        message: trap.to_string().lines().next().unwrap_or_default().to_owned(),
+        backtrace: trap
+            .trace()
+            .iter()
+            .map(|frame| {
+                format!(
+                    "{}!{}",
+                    frame.module_name().unwrap_or("<module>"),
+                    frame.func_name().unwrap_or("<unknown>")
+                )
+            })
+            .collect(),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ExecutionError {
+    #[error("Unknown module {0:?}")]
+    ModuleNotFound(String),
+
+    #[error("Module {0:?} is not deployed")]
+    NotDeployed(String),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("Invalid output: {0}")]
+    InvalidOutput(String),
+
+    #[error("Guest trapped: {message}")]
+    Trap {
+        trap_code: Option<TrapCode>,
+        message: String,
+        /// Wasm frames, innermost first
+        backtrace: Vec<String>,
+    },
+
+    #[error("Guest exited with code {0}")]
+    NonZeroExit(i32),
+
+    #[error("Fuel budget of {0} units exhausted")]
+    FuelExhausted(u64),
+
+    #[error("Execution timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error("Memory limit of {0} bytes exceeded")]
+    MemoryLimitExceeded(usize),
+
+    /// The module cannot run whatever the input, e.g. it lacks a default export
+    #[error("Invalid module: {0}")]
+    InvalidModule(anyhow::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// Host side failure, e.g. running out of resources while instantiating the guest
+    #[error(transparent)]
+    Failure(#[from] anyhow::Error),
+}
+
+impl ExecutionError {
+    /// Whether running the same input again may succeed, i.e. host I/O and resource failures as
+    /// opposed to failures caused by the input or the module itself. Timeouts are not, the same
+    /// input most likely running as long
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ExecutionError::Io(_) | ExecutionError::Failure(_))
+    }
 }
 
 impl Compiler {
@@ -51,17 +271,45 @@ impl Compiler {
             .read_to_end(&mut buff)
             .expect("Cannot use reader during compilation");
         match Module::new(&self.engine, buff) {
-            Ok(module) => {
-                let compilation_unit = CompilationUnit { module };
-                if let Some(validation_error) = get_validation_errors(&compilation_unit) {
-                    Err(validation_error)
-                } else {
-                    Ok(compilation_unit)
+            Ok(module) => match self.pre_instantiate(&module) {
+                Ok(instance_pre) => {
+                    let compilation_unit = CompilationUnit {
+                        module,
+                        instance_pre,
+                        assets: None,
+                        env: vec![],
+                    };
+                    if let Some(validation_error) = get_validation_errors(&compilation_unit) {
+                        Err(validation_error)
+                    } else {
+                        Ok(compilation_unit)
+                    }
                 }
-            }
+                Err(error) => Err(format!("{:?}", error)),
+            },
             Err(error) => Err(format!("{:?}", error)),
         }
     }
+
+    /// Links the module against WASI once, so executions only have to create a store and
+    /// instantiate it
+    fn pre_instantiate(&self, module: &Module) -> anyhow::Result<InstancePre<StoreState>> {
+        let mut linker = Linker::new(&self.engine);
+        wasmtime_wasi::add_to_linker(&mut linker, |state: &mut StoreState| &mut state.wasi)?;
+        let state = StoreState::new(WasiCtxBuilder::new().build(), ExecutionLimits::default());
+        let mut store = Store::new(&self.engine, state);
+        linker.instantiate_pre(&mut store, module)
+    }
+}
+
+/// Command modules export `_start`, reactors may export a function named as the empty string
+fn default_export(instance: &Instance, store: &mut Store<StoreState>) -> Result<TypedFunc<(), ()>, ExecutionError> {
+    instance
+        .get_func(store.as_context_mut(), "")
+        .or_else(|| instance.get_func(store.as_context_mut(), "_start"))
+        .ok_or_else(|| anyhow::anyhow!("Cannot find default export in module"))
+        .and_then(|func| func.typed::<(), (), _>(store.as_context()))
+        .map_err(ExecutionError::InvalidModule)
 }
 
 fn get_validation_errors(compilation_unit: &CompilationUnit) -> Option<String> {
@@ -85,49 +333,168 @@ fn get_validation_errors(compilation_unit: &CompilationUnit) -> Option<String> {
 
 pub struct Executor {
     engine: Arc<Engine>,
+    async_support: bool,
 }
 
 impl Executor {
     pub fn new(engine: Arc<Engine>) -> Executor {
-        Executor { engine }
+        Executor {
+            engine,
+            async_support: false,
+        }
     }
 
     pub fn execute(
         &self,
         compilation_unit: &Option<CompilationUnit>,
-        frame: &DataFrame,
-    ) -> anyhow::Result<DataFrame> {
-        let mut input_file = memfile::MemFile::create_default("tmp-stdin")?;
-        let mut output_file = memfile::MemFile::create_default("tmp-stdout")?;
+        limits: &ExecutionLimits,
+        frame: DataFrame,
+        stderr: &StderrCapture,
+    ) -> Result<DataFrame, ExecutionError> {
+        let stdin = ReadPipe::from(frame.body);
+        let stdout = WritePipe::new_in_memory();
+        self.run(compilation_unit, limits, &frame.metadata, Box::new(stdin), Box::new(stdout.clone()), stderr)?;
+        collect_output(stdout)
+    }
 
-        input_file.write_all(&frame.body)?;
-        input_file.seek(SeekFrom::Start(0))?;
+    /// Feeds the guest stdin from `input` and hands its stdout over to `output` as it is
+    /// written, so neither has to fit in memory at once
+    pub fn execute_stream<R, W>(
+        &self,
+        compilation_unit: &Option<CompilationUnit>,
+        limits: &ExecutionLimits,
+        metadata: &Metadata,
+        input: R,
+        output: W,
+        stderr: &StderrCapture,
+    ) -> Result<(), ExecutionError>
+    where
+        R: Read + Any + Send + Sync,
+        W: Write + Any + Send + Sync,
+    {
+        self.run(
+            compilation_unit,
+            limits,
+            metadata,
+            Box::new(ReadPipe::new(input)),
+            Box::new(WritePipe::new(output)),
+            stderr,
+        )
+    }
+
+    /// Same as `execute` but yielding to the async runtime periodically, so a single thread can
+    /// drive many executions at once
+    pub async fn execute_async(
+        &self,
+        compilation_unit: &Option<CompilationUnit>,
+        limits: &ExecutionLimits,
+        frame: DataFrame,
+        stderr: &StderrCapture,
+    ) -> Result<DataFrame, ExecutionError> {
+        if !self.async_support {
+            return Err(anyhow::anyhow!("Sync executors can only run through execute").into());
+        }
+        let compilation_unit = compilation_unit.as_ref().unwrap();
+        let stdin = ReadPipe::from(frame.body);
+        let stdout = WritePipe::new_in_memory();
+        let mut store = self.new_store(
+            compilation_unit,
+            limits,
+            &frame.metadata,
+            Box::new(stdin),
+            Box::new(stdout.clone()),
+            stderr,
+        )?;
+        // the first top-up takes the part of the budget that is not a multiple of YIELD_FUEL, so
+        // the guest never burns more than its budget
+        let budget = limits.fuel.unwrap_or(u64::MAX);
+        let injections = budget.saturating_sub(1) / YIELD_FUEL;
+        store.add_fuel(budget - injections * YIELD_FUEL)?;
+        store.out_of_fuel_async_yield(injections, YIELD_FUEL);
+
+        let instantiated = compilation_unit.instance_pre.instantiate_async(store.as_context_mut()).await;
+        let instance = match instantiated {
+            Ok(instance) => instance,
+            Err(err) => return Err(classify_failure(err, &store, limits)),
+        };
+        let func = default_export(&instance, &mut store)?;
+        let run_result = func.call_async(store.as_context_mut(), ()).await.map_err(anyhow::Error::from);
+        if let Err(err) = ignore_clean_exit(run_result) {
+            return Err(classify_failure(err, &store, limits));
+        }
+        // the store holds the other end of the stdout pipe
+        drop(store);
+        collect_output(stdout)
+    }
+
+    fn run(
+        &self,
+        compilation_unit: &Option<CompilationUnit>,
+        limits: &ExecutionLimits,
+        metadata: &Metadata,
+        stdin: Box<dyn WasiFile>,
+        stdout: Box<dyn WasiFile>,
+        stderr: &StderrCapture,
+    ) -> Result<(), ExecutionError> {
+        if self.async_support {
+            return Err(anyhow::anyhow!("Async executors can only run through execute_async").into());
+        }
+        let compilation_unit = compilation_unit.as_ref().unwrap();
+        let mut store = self.new_store(compilation_unit, limits, metadata, stdin, stdout, stderr)?;
+        store.add_fuel(limits.fuel.unwrap_or(u64::MAX))?;
 
-        let stdin = Box::new(wasi_common::pipe::ReadPipe::from_shared(Arc::new(RwLock::new(input_file))));
-        let output_guarded = Arc::new(RwLock::new(output_file.try_clone().unwrap()));
-        let mut stdout = Box::new(wasi_common::pipe::WritePipe::from_shared(output_guarded.clone()));
-        let stderr = Box::new(wasi_common::pipe::WritePipe::from_shared(Arc::new(RwLock::new(stderr()))));
+        let instance = compilation_unit
+            .instance_pre
+            .instantiate(store.as_context_mut())
+            .map_err(|err| classify_failure(err, &store, limits))?;
+        let func = default_export(&instance, &mut store)?;
+        let run_result = func.call(store.as_context_mut(), ()).map_err(anyhow::Error::from);
+        ignore_clean_exit(run_result).map_err(|err| classify_failure(err, &store, limits))
+    }
 
-        let mut ctx = WasiCtxBuilder::new();
-        let mut wasi_ctx = ctx
+    fn new_store(
+        &self,
+        compilation_unit: &CompilationUnit,
+        limits: &ExecutionLimits,
+        metadata: &Metadata,
+        stdin: Box<dyn WasiFile>,
+        stdout: Box<dyn WasiFile>,
+        stderr: &StderrCapture,
+    ) -> Result<Store<StoreState>, ExecutionError> {
+        let mut wasi_builder = WasiCtxBuilder::new()
             .stdin(stdin)
             .stdout(stdout)
-            .stderr(stderr)
-            .build();
-        let mut store = Box::new(Store::new(&self.engine, wasi_ctx));
-        let mut linker = Linker::new(&self.engine);
-        wasmtime_wasi::add_to_linker(&mut linker, |s| s)?;
-
-        linker.module(store.as_context_mut(), "", &compilation_unit.as_ref().unwrap().module)?;
-        linker.get_default(store.as_context_mut(), "")?
-            .typed::<(), (), _>(store.as_context_mut())?
-            .call(store.as_context_mut(), ())?;
-        let mut buffer = vec![];
-        let mut output = output_guarded.write().unwrap();
-        output_file.seek(SeekFrom::Start(0))?;
-        output_file.read_to_end(&mut buffer)?;
-        Ok(DataFrame {
-            body: buffer
-        })
+            .stderr(Box::new(WritePipe::new(stderr.clone())))
+            .envs(&compilation_unit.env)
+            .map_err(anyhow::Error::from)?
+            .envs(&metadata.env())
+            .map_err(anyhow::Error::from)?;
+        if let Some(assets) = &compilation_unit.assets {
+            let dir = Dir::open_ambient_dir(assets, ambient_authority())?;
+            wasi_builder = wasi_builder.preopened_dir(dir, "/assets")?;
+        }
+        let wasi_ctx = wasi_builder.build();
+        let mut store = Store::new(&self.engine, StoreState::new(wasi_ctx, *limits));
+        store.limiter(|state| &mut state.limiter);
+        store.set_epoch_deadline(epoch_deadline(limits));
+        Ok(store)
     }
 }
+
+/// WASI `proc_exit(0)` unwinds the guest through a trap, the run still succeeded
+fn ignore_clean_exit(run_result: anyhow::Result<()>) -> anyhow::Result<()> {
+    match run_result {
+        Err(err) if err.downcast_ref::<Trap>().and_then(|trap| trap.i32_exit_status()) == Some(0) => {
+            Ok(())
+        }
+        run_result => run_result,
+    }
+}
+
+fn collect_output(stdout: WritePipe<Cursor<Vec<u8>>>) -> Result<DataFrame, ExecutionError> {
+    let buffer = stdout
+        .try_into_inner()
+        .map_err(|_| anyhow::anyhow!("Guest stdout is still shared"))?
+        .into_inner();
+    Ok(DataFrame::new(buffer))
+}
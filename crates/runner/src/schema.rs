@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+/// Mirror of `fn_proto.SchemaFormat`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SchemaFormat {
+    #[default]
+    Avro,
+    Protobuf,
+    JsonSchema,
+}
+
+/// Mirror of `fn_proto.Field`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: String,
+    /// Protobuf field number, the position of the field in its record from 1 when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number: Option<u32>,
+}
+
+/// Mirror of `fn_proto.Record`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub name: String,
+    pub fields: Vec<Field>,
+}
+
+/// Mirror of `fn_proto.Schema`, the first record describing the message itself
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    pub format: SchemaFormat,
+    pub records: Vec<Record>,
+}
+
+/// Input and output schemas registered for a module, an input schema sent along with a request
+/// wins over the registered one
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModuleSchemas {
+    pub input: Option<Schema>,
+    pub output: Option<Schema>,
+    /// Content type outputs get encoded in, the one of the input when unset
+    pub output_content_type: Option<String>,
+}
+
+/// Mismatch between a JSON value and a schema, `path` pointing at the offending field
+#[derive(Error, Debug, PartialEq)]
+#[error("{path}: {message}")]
+pub struct SchemaError {
+    pub path: String,
+    pub message: String,
+}
+
+impl SchemaError {
+    pub(crate) fn new(path: &str, message: String) -> SchemaError {
+        SchemaError {
+            path: path.to_owned(),
+            message,
+        }
+    }
+}
+
+impl Schema {
+    /// Checks `value` against the first record of the schema, the other records only being
+    /// referenced by field types.
+    ///
+    /// Field types are either a primitive (`null`, `boolean`, `int`, `long`, `float`, `double`,
+    /// `string`, `bytes`, `any` and their protobuf/JSON schema spellings) or a record name, followed
+    /// by `[]` for arrays and `?` for optional fields. Fields missing from the value are treated as
+    /// `null` and fields not declared by a record are let through.
+    pub fn validate(&self, value: &Value) -> Result<(), SchemaError> {
+        match self.records.first() {
+            Some(record) => self.check_record(record, value, "$"),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn record(&self, name: &str) -> Option<&Record> {
+        self.records.iter().find(|record| record.name == name)
+    }
+
+    fn check_record(&self, record: &Record, value: &Value, path: &str) -> Result<(), SchemaError> {
+        let object = value.as_object().ok_or_else(|| {
+            SchemaError::new(path, format!("expected a {} record, found {}", record.name, kind(value)))
+        })?;
+        for field in &record.fields {
+            let field_path = format!("{}.{}", path, field.name);
+            let field_value = object.get(&field.name).unwrap_or(&Value::Null);
+            self.check(&field.field_type, field_value, &field_path)?;
+        }
+        Ok(())
+    }
+
+    fn check(&self, field_type: &str, value: &Value, path: &str) -> Result<(), SchemaError> {
+        let field_type = field_type.trim();
+        if let Some(inner) = field_type.strip_suffix('?') {
+            return match value {
+                Value::Null => Ok(()),
+                _ => self.check(inner, value, path),
+            };
+        }
+        if let Some(inner) = field_type.strip_suffix("[]") {
+            let items = value
+                .as_array()
+                .ok_or_else(|| SchemaError::new(path, format!("expected an array, found {}", kind(value))))?;
+            for (i, item) in items.iter().enumerate() {
+                self.check(inner, item, &format!("{}[{}]", path, i))?;
+            }
+            return Ok(());
+        }
+        let matches = match field_type {
+            "any" => true,
+            "null" => value.is_null(),
+            "boolean" | "bool" => value.is_boolean(),
+            "int" | "int32" | "sint32" | "sfixed32" => {
+                value.as_i64().is_some_and(|n| i32::try_from(n).is_ok())
+            }
+            "uint32" | "fixed32" => value.as_u64().is_some_and(|n| u32::try_from(n).is_ok()),
+            "long" | "int64" | "sint64" | "sfixed64" | "integer" => value.is_i64(),
+            "uint64" | "fixed64" => value.is_u64(),
+            "float" | "double" | "number" => value.is_number(),
+            "string" | "bytes" => value.is_string(),
+            name => {
+                let record = self
+                    .record(name)
+                    .ok_or_else(|| SchemaError::new(path, format!("unknown type {}", name)))?;
+                return self.check_record(record, value, path);
+            }
+        };
+        if matches {
+            Ok(())
+        } else {
+            Err(SchemaError::new(path, format!("expected {}, found {}", field_type, kind(value))))
+        }
+    }
+}
+
+pub(crate) fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
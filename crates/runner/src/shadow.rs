@@ -0,0 +1,235 @@
+use crate::data::DataFrame;
+use crate::runner::ExecutionError;
+
+use serde_json::{Number, Value};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Differing calls kept as samples, the oldest being dropped first
+const MAX_SAMPLES: usize = 20;
+/// Differences kept per sample, the counts still cover every one
+const MAX_SAMPLE_DIFFERENCES: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DifferenceKind {
+    /// Only in the active output
+    Removed,
+    /// Only in the candidate output
+    Added,
+    Changed,
+}
+
+/// Single spot where the candidate output departs from the active one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    /// Where the values sit, e.g. `$.lines[2].price`
+    pub path: String,
+    pub kind: DifferenceKind,
+    pub active: Option<Value>,
+    pub candidate: Option<Value>,
+}
+
+/// Structural differences between two JSON values, numbers being compared by value so `1` and
+/// `1.0` are the same
+pub fn json_diff(active: &Value, candidate: &Value) -> Vec<Difference> {
+    let mut differences = vec![];
+    diff_at("$", active, candidate, &mut differences);
+    differences
+}
+
+fn diff_at(path: &str, active: &Value, candidate: &Value, differences: &mut Vec<Difference>) {
+    match (active, candidate) {
+        (Value::Object(active), Value::Object(candidate)) => {
+            for (name, active_value) in active {
+                let value_path = format!("{}.{}", path, name);
+                match candidate.get(name) {
+                    Some(candidate_value) => diff_at(&value_path, active_value, candidate_value, differences),
+                    None => differences.push(removed(value_path, active_value)),
+                }
+            }
+            for (name, candidate_value) in candidate.iter().filter(|(name, _)| !active.contains_key(*name)) {
+                differences.push(added(format!("{}.{}", path, name), candidate_value));
+            }
+        }
+        (Value::Array(active), Value::Array(candidate)) => {
+            for i in 0..active.len().max(candidate.len()) {
+                let item_path = format!("{}[{}]", path, i);
+                match (active.get(i), candidate.get(i)) {
+                    (Some(active_item), Some(candidate_item)) => {
+                        diff_at(&item_path, active_item, candidate_item, differences)
+                    }
+                    (Some(active_item), None) => differences.push(removed(item_path, active_item)),
+                    (None, Some(candidate_item)) => differences.push(added(item_path, candidate_item)),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::Number(active_number), Value::Number(candidate_number))
+            if same_number(active_number, candidate_number) => {}
+        _ if active == candidate => {}
+        _ => differences.push(Difference {
+            path: path.to_owned(),
+            kind: DifferenceKind::Changed,
+            active: Some(active.clone()),
+            candidate: Some(candidate.clone()),
+        }),
+    }
+}
+
+/// Integers are compared exactly, as above 2^53 distinct ones can share the same `f64`
+fn same_number(active: &Number, candidate: &Number) -> bool {
+    if active.is_f64() || candidate.is_f64() {
+        return active.as_f64() == candidate.as_f64();
+    }
+    match (active.as_i64(), candidate.as_i64()) {
+        (Some(active), Some(candidate)) => active == candidate,
+        (None, None) => active.as_u64() == candidate.as_u64(),
+        _ => false,
+    }
+}
+
+fn removed(path: String, active: &Value) -> Difference {
+    Difference {
+        path,
+        kind: DifferenceKind::Removed,
+        active: Some(active.clone()),
+        candidate: None,
+    }
+}
+
+fn added(path: String, candidate: &Value) -> Difference {
+    Difference {
+        path,
+        kind: DifferenceKind::Added,
+        active: None,
+        candidate: Some(candidate.clone()),
+    }
+}
+
+/// Array indices folded so every item of an array counts under the same path, e.g. `$.lines[*]`
+fn count_path(path: &str) -> String {
+    let mut folded = String::with_capacity(path.len());
+    let mut in_index = false;
+    for c in path.chars() {
+        match c {
+            '[' => {
+                in_index = true;
+                folded.push_str("[*");
+            }
+            ']' => {
+                in_index = false;
+                folded.push(']');
+            }
+            _ if in_index => {}
+            _ => folded.push(c),
+        }
+    }
+    folded
+}
+
+/// Differing call of a shadow run
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiffSample {
+    /// Invocation of the active version
+    pub invocation_id: u64,
+    pub differences: Vec<Difference>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShadowSnapshot {
+    pub candidate_version: String,
+    pub compared: u64,
+    pub identical: u64,
+    pub different: u64,
+    /// Calls the active version ran fine but the candidate failed
+    pub candidate_failures: u64,
+    /// Calls whose outputs are not both JSON, so they could not be compared
+    pub skipped: u64,
+    /// Differing calls per path, array indices folded into `[*]`
+    pub path_counts: BTreeMap<String, u64>,
+    /// Latest differing calls, oldest first
+    pub samples: Vec<DiffSample>,
+}
+
+#[derive(Default)]
+struct ShadowState {
+    compared: u64,
+    identical: u64,
+    different: u64,
+    candidate_failures: u64,
+    skipped: u64,
+    path_counts: BTreeMap<String, u64>,
+    samples: VecDeque<DiffSample>,
+}
+
+/// How the outputs of a candidate version compare with those of the active one on the same
+/// inputs, shared between the manager and the routes shadowing calls
+#[derive(Clone)]
+pub struct ShadowReport {
+    pub candidate_version: String,
+    state: Arc<Mutex<ShadowState>>,
+}
+
+impl ShadowReport {
+    pub fn new(candidate_version: String) -> ShadowReport {
+        ShadowReport {
+            candidate_version,
+            state: Arc::new(Mutex::new(ShadowState::default())),
+        }
+    }
+
+    /// Compares the output of a successful active call with the candidate result on the same input
+    pub fn record(&self, invocation_id: u64, active: &DataFrame, candidate: &Result<DataFrame, ExecutionError>) {
+        let mut state = self.state.lock().unwrap();
+        state.compared += 1;
+        let candidate = match candidate {
+            Ok(candidate) => candidate,
+            Err(_) => {
+                state.candidate_failures += 1;
+                return;
+            }
+        };
+        let values = serde_json::from_slice::<Value>(&active.body)
+            .and_then(|active| serde_json::from_slice::<Value>(&candidate.body).map(|candidate| (active, candidate)));
+        let differences = match values {
+            Ok((active, candidate)) => json_diff(&active, &candidate),
+            Err(_) => {
+                state.skipped += 1;
+                return;
+            }
+        };
+        if differences.is_empty() {
+            state.identical += 1;
+            return;
+        }
+        state.different += 1;
+        let paths = differences
+            .iter()
+            .map(|difference| count_path(&difference.path))
+            .collect::<BTreeSet<String>>();
+        for path in paths {
+            *state.path_counts.entry(path).or_default() += 1;
+        }
+        if state.samples.len() == MAX_SAMPLES {
+            state.samples.pop_front();
+        }
+        state.samples.push_back(DiffSample {
+            invocation_id,
+            differences: differences.into_iter().take(MAX_SAMPLE_DIFFERENCES).collect(),
+        });
+    }
+
+    pub fn snapshot(&self) -> ShadowSnapshot {
+        let state = self.state.lock().unwrap();
+        ShadowSnapshot {
+            candidate_version: self.candidate_version.clone(),
+            compared: state.compared,
+            identical: state.identical,
+            different: state.different,
+            candidate_failures: state.candidate_failures,
+            skipped: state.skipped,
+            path_counts: state.path_counts.clone(),
+            samples: state.samples.iter().cloned().collect(),
+        }
+    }
+}
@@ -0,0 +1,653 @@
+use crate::schema::{kind, Field, Record, Schema, SchemaError};
+
+use apache_avro::types::Value as AvroValue;
+use apache_avro::Schema as AvroSchema;
+use serde_json::{json, Map, Number, Value};
+use std::collections::{HashMap, HashSet};
+
+/// Integer field types, all carried as Avro longs
+const INTEGER_TYPES: &[&str] = &[
+    "int", "int32", "sint32", "sfixed32", "uint32", "fixed32", "long", "int64", "sint64",
+    "sfixed64", "uint64", "fixed64", "integer",
+];
+
+/// Longest chain of nested records a schema may declare, which bounds how deep both codecs recurse
+const MAX_DEPTH: usize = 32;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_64_BIT: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_32_BIT: u8 = 5;
+
+/// Encoding of a body, told apart by its content type
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayloadFormat {
+    Json,
+    /// Avro binary datum, without container nor schema registry framing
+    Avro,
+    /// Protobuf wire format, fields declaring no number being numbered after their position from 1
+    Protobuf,
+    MessagePack,
+    Cbor,
+}
+
+impl PayloadFormat {
+    /// Bodies without a content type are taken as JSON, `None` standing for opaque bodies
+    pub fn from_content_type(content_type: Option<&str>) -> Option<PayloadFormat> {
+        match content_type {
+            None => Some(PayloadFormat::Json),
+            Some(content_type) if content_type.contains("json") => Some(PayloadFormat::Json),
+            Some(content_type) if content_type.contains("avro") => Some(PayloadFormat::Avro),
+            Some(content_type) if content_type.contains("protobuf") => Some(PayloadFormat::Protobuf),
+            Some(content_type) if content_type.contains("msgpack") || content_type.contains("messagepack") => {
+                Some(PayloadFormat::MessagePack)
+            }
+            Some(content_type) if content_type.contains("cbor") => Some(PayloadFormat::Cbor),
+            Some(_) => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            PayloadFormat::Json => "application/json",
+            PayloadFormat::Avro => "application/avro",
+            PayloadFormat::Protobuf => "application/x-protobuf",
+            PayloadFormat::MessagePack => "application/msgpack",
+            PayloadFormat::Cbor => "application/cbor",
+        }
+    }
+
+    /// Whether bodies only decode with a schema, the guest getting them as JSON. The other
+    /// formats reach the guest as they are
+    pub fn needs_schema(&self) -> bool {
+        matches!(self, PayloadFormat::Avro | PayloadFormat::Protobuf)
+    }
+}
+
+/// Value of a body in a format decoding without a schema
+pub fn to_value(format: PayloadFormat, body: &[u8]) -> Result<Value, SchemaError> {
+    let value = match format {
+        PayloadFormat::Json => serde_json::from_slice(body).map_err(|err| format!("body is not valid JSON: {}", err)),
+        PayloadFormat::MessagePack => {
+            rmp_serde::from_slice(body).map_err(|err| format!("body is not valid MessagePack: {}", err))
+        }
+        PayloadFormat::Cbor => serde_cbor::from_slice(body).map_err(|err| format!("body is not valid CBOR: {}", err)),
+        PayloadFormat::Avro | PayloadFormat::Protobuf => Err(format!("{} bodies need a schema", format.content_type())),
+    };
+    value.map_err(|message| SchemaError::new("$", message))
+}
+
+/// Decodes a `format` body into JSON, following the first record of `schema` for Avro and
+/// Protobuf
+pub fn to_json(format: PayloadFormat, schema: &Schema, body: &[u8]) -> Result<Vec<u8>, SchemaError> {
+    let value = match format {
+        PayloadFormat::Json => return Ok(body.to_vec()),
+        PayloadFormat::Avro => {
+            let avro_schema = avro_schema(schema, root_record(schema)?)?;
+            let mut input = body;
+            let value = apache_avro::from_avro_datum(&avro_schema, &mut input, None)
+                .map_err(|err| SchemaError::new("$", format!("invalid Avro datum: {}", err)))?;
+            if !input.is_empty() {
+                return Err(SchemaError::new("$", "trailing bytes after the record".to_owned()));
+            }
+            avro_to_json(value)
+        }
+        PayloadFormat::Protobuf => proto_decode_record(schema, root_record(schema)?, body, "$")?,
+        PayloadFormat::MessagePack | PayloadFormat::Cbor => to_value(format, body)?,
+    };
+    Ok(serde_json::to_vec(&value).unwrap())
+}
+
+/// Encodes a JSON body into `format`, following the first record of `schema` for Avro and
+/// Protobuf
+pub fn from_json(format: PayloadFormat, schema: &Schema, body: &[u8]) -> Result<Vec<u8>, SchemaError> {
+    if format == PayloadFormat::Json {
+        return Ok(body.to_vec());
+    }
+    let value = to_value(PayloadFormat::Json, body)?;
+    match format {
+        PayloadFormat::MessagePack => return Ok(rmp_serde::to_vec_named(&value).unwrap()),
+        PayloadFormat::Cbor => return Ok(serde_cbor::to_vec(&value).unwrap()),
+        _ => {}
+    }
+    let record = root_record(schema)?;
+    if format == PayloadFormat::Avro {
+        let avro_schema = avro_schema(schema, record)?;
+        let value = avro_record_value(schema, record, &value, "$")?;
+        return apache_avro::to_avro_datum(&avro_schema, value)
+            .map_err(|err| SchemaError::new("$", format!("cannot encode Avro datum: {}", err)));
+    }
+    let mut output = vec![];
+    proto_encode_record(schema, record, &value, &mut output, "$")?;
+    Ok(output)
+}
+
+/// First record of `schema`, once its records are checked to nest neither recursively nor deeper
+/// than `MAX_DEPTH`
+fn root_record(schema: &Schema) -> Result<&Record, SchemaError> {
+    let record = schema
+        .records
+        .first()
+        .ok_or_else(|| SchemaError::new("$", "schema has no records".to_owned()))?;
+    check_nesting(schema, record, 1, &mut HashMap::new(), "$")?;
+    Ok(record)
+}
+
+/// Number of records nested in `record`, itself included, `heights` holding the ones already
+/// checked and `None` for the ones being checked
+fn check_nesting<'a>(
+    schema: &'a Schema,
+    record: &'a Record,
+    level: usize,
+    heights: &mut HashMap<&'a str, Option<usize>>,
+    path: &str,
+) -> Result<usize, SchemaError> {
+    let too_deep = || SchemaError::new(path, format!("records nest deeper than {} levels", MAX_DEPTH));
+    let height = match heights.get(record.name.as_str()) {
+        Some(Some(height)) => *height,
+        Some(None) => return Err(SchemaError::new(path, format!("record {} references itself", record.name))),
+        None if level > MAX_DEPTH => return Err(too_deep()),
+        None => {
+            heights.insert(&record.name, None);
+            let mut height = 1;
+            for field in &record.fields {
+                let name = field.field_type.trim().trim_end_matches(&['?', '[', ']'][..]);
+                if let Some(nested) = schema.record(name) {
+                    let field_path = format!("{}.{}", path, field.name);
+                    height = height.max(1 + check_nesting(schema, nested, level + 1, heights, &field_path)?);
+                }
+            }
+            heights.insert(&record.name, Some(height));
+            height
+        }
+    };
+    if level + height - 1 > MAX_DEPTH {
+        return Err(too_deep());
+    }
+    Ok(height)
+}
+
+fn record_for<'a>(schema: &'a Schema, name: &str, path: &str) -> Result<&'a Record, SchemaError> {
+    schema
+        .record(name)
+        .ok_or_else(|| SchemaError::new(path, format!("cannot transcode type {}", name)))
+}
+
+fn mismatch(path: &str, expected: &str, value: &Value) -> SchemaError {
+    SchemaError::new(path, format!("expected {}, found {}", expected, kind(value)))
+}
+
+fn float(value: f64) -> Value {
+    Number::from_f64(value).map(Value::Number).unwrap_or(Value::Null)
+}
+
+/// Bytes are carried in JSON as strings of code points up to 255, as the Avro JSON encoding does
+fn bytes_to_string(bytes: &[u8]) -> Value {
+    Value::String(bytes.iter().map(|&byte| byte as char).collect())
+}
+
+fn string_to_bytes(value: &Value, path: &str) -> Result<Vec<u8>, SchemaError> {
+    let string = value.as_str().ok_or_else(|| mismatch(path, "bytes", value))?;
+    string
+        .chars()
+        .map(|c| u8::try_from(c as u32))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| SchemaError::new(path, "bytes cannot hold code points above 255".to_owned()))
+}
+
+fn utf8(bytes: &[u8], path: &str) -> Result<Value, SchemaError> {
+    String::from_utf8(bytes.to_vec())
+        .map(Value::String)
+        .map_err(|_| SchemaError::new(path, "string is not valid UTF-8".to_owned()))
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    ((n >> 1) as i64) ^ -((n & 1) as i64)
+}
+
+/// `value` as the narrower integer `field_type` is carried in, failing rather than truncating it
+fn in_range<T, V>(value: V, field_type: &str, path: &str) -> Result<T, SchemaError>
+where
+    T: TryFrom<V>,
+    V: Copy + std::fmt::Display,
+{
+    T::try_from(value).map_err(|_| SchemaError::new(path, format!("{} is out of range for {}", value, field_type)))
+}
+
+fn write_varint(mut n: u64, output: &mut Vec<u8>) {
+    while n >= 0x80 {
+        output.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    output.push(n as u8);
+}
+
+struct Input<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    fn new(bytes: &'a [u8]) -> Input<'a> {
+        Input { bytes }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn take(&mut self, len: usize, path: &str) -> Result<&'a [u8], SchemaError> {
+        if len > self.bytes.len() {
+            return Err(SchemaError::new(path, "payload ends early".to_owned()));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self, path: &str) -> Result<[u8; N], SchemaError> {
+        Ok(self.take(N, path)?.try_into().unwrap())
+    }
+
+    fn varint(&mut self, path: &str) -> Result<u64, SchemaError> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1, path)?[0];
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(SchemaError::new(path, "varint is too long".to_owned()))
+    }
+
+    fn len_delimited(&mut self, path: &str) -> Result<&'a [u8], SchemaError> {
+        let len = self.varint(path)?;
+        self.take(len as usize, path)
+    }
+}
+
+/// Avro schema of `record`, each record it nests being defined where first used
+fn avro_schema(schema: &Schema, record: &Record) -> Result<AvroSchema, SchemaError> {
+    let (avro_record, _) = avro_record_type(schema, record, &mut HashMap::new(), "$")?;
+    AvroSchema::parse(&avro_record).map_err(|err| SchemaError::new("$", format!("invalid Avro schema: {}", err)))
+}
+
+/// Avro definition of `record` and whether its values take any bytes, `defined` holding the same
+/// for the records already defined
+fn avro_record_type(
+    schema: &Schema,
+    record: &Record,
+    defined: &mut HashMap<String, bool>,
+    path: &str,
+) -> Result<(Value, bool), SchemaError> {
+    let mut fields = vec![];
+    let mut sized = false;
+    for field in &record.fields {
+        let field_path = format!("{}.{}", path, field.name);
+        let (field_type, field_sized) = avro_type(schema, &field.field_type, defined, &field_path)?;
+        sized |= field_sized;
+        fields.push(json!({"name": field.name, "type": field_type}));
+    }
+    defined.insert(record.name.clone(), sized);
+    Ok((json!({"type": "record", "name": record.name, "fields": fields}), sized))
+}
+
+fn avro_type(
+    schema: &Schema,
+    field_type: &str,
+    defined: &mut HashMap<String, bool>,
+    path: &str,
+) -> Result<(Value, bool), SchemaError> {
+    let field_type = field_type.trim();
+    if let Some(inner) = field_type.strip_suffix('?') {
+        let (inner, _) = avro_type(schema, inner, defined, path)?;
+        return Ok((json!(["null", inner]), true));
+    }
+    if let Some(inner) = field_type.strip_suffix("[]") {
+        let (items, sized) = avro_type(schema, inner, defined, path)?;
+        if !sized {
+            // every item taking a byte bounds the length of arrays by the one of the datum
+            return Err(SchemaError::new(path, format!("array items of type {} take no bytes", inner)));
+        }
+        return Ok((json!({"type": "array", "items": items}), true));
+    }
+    let primitive = match field_type {
+        "null" => return Ok((json!("null"), false)),
+        "boolean" | "bool" => "boolean",
+        "float" => "float",
+        "double" | "number" => "double",
+        "string" => "string",
+        "bytes" => "bytes",
+        integer if INTEGER_TYPES.contains(&integer) => "long",
+        name => {
+            if let Some(sized) = defined.get(name) {
+                return Ok((json!(name), *sized));
+            }
+            return avro_record_type(schema, record_for(schema, name, path)?, defined, path);
+        }
+    };
+    Ok((json!(primitive), true))
+}
+
+/// JSON form of a decoded Avro value, unions being unwrapped and bytes carried as strings
+fn avro_to_json(value: AvroValue) -> Value {
+    match value {
+        AvroValue::Null => Value::Null,
+        AvroValue::Boolean(boolean) => Value::Bool(boolean),
+        AvroValue::Int(n) => Value::from(n),
+        AvroValue::Long(n) => Value::from(n),
+        AvroValue::Float(n) => float(n as f64),
+        AvroValue::Double(n) => float(n),
+        AvroValue::String(string) => Value::String(string),
+        AvroValue::Bytes(bytes) => bytes_to_string(&bytes),
+        AvroValue::Union(_, value) => avro_to_json(*value),
+        AvroValue::Array(items) => Value::Array(items.into_iter().map(avro_to_json).collect()),
+        AvroValue::Record(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name, avro_to_json(value)))
+                .collect(),
+        ),
+        // the schemas built out of records hold no other types
+        _ => Value::Null,
+    }
+}
+
+fn avro_record_value(schema: &Schema, record: &Record, value: &Value, path: &str) -> Result<AvroValue, SchemaError> {
+    let object = value.as_object().ok_or_else(|| mismatch(path, &record.name, value))?;
+    let fields = record
+        .fields
+        .iter()
+        .map(|field| {
+            let field_path = format!("{}.{}", path, field.name);
+            let field_value = object.get(&field.name).unwrap_or(&Value::Null);
+            Ok((field.name.clone(), avro_value(schema, &field.field_type, field_value, &field_path)?))
+        })
+        .collect::<Result<Vec<(String, AvroValue)>, SchemaError>>()?;
+    Ok(AvroValue::Record(fields))
+}
+
+fn avro_value(schema: &Schema, field_type: &str, value: &Value, path: &str) -> Result<AvroValue, SchemaError> {
+    let field_type = field_type.trim();
+    if let Some(inner) = field_type.strip_suffix('?') {
+        // optional fields are ["null", T] unions
+        return match value {
+            Value::Null => Ok(AvroValue::Union(0, Box::new(AvroValue::Null))),
+            value => Ok(AvroValue::Union(1, Box::new(avro_value(schema, inner, value, path)?))),
+        };
+    }
+    if let Some(inner) = field_type.strip_suffix("[]") {
+        let items = value.as_array().ok_or_else(|| mismatch(path, "an array", value))?;
+        return items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| avro_value(schema, inner, item, &format!("{}[{}]", path, i)))
+            .collect::<Result<Vec<AvroValue>, SchemaError>>()
+            .map(AvroValue::Array);
+    }
+    let number = || value.as_f64().ok_or_else(|| mismatch(path, field_type, value));
+    let avro_value = match field_type {
+        "null" if value.is_null() => AvroValue::Null,
+        "boolean" | "bool" => AvroValue::Boolean(value.as_bool().ok_or_else(|| mismatch(path, field_type, value))?),
+        "float" => AvroValue::Float(number()? as f32),
+        "double" | "number" => AvroValue::Double(number()?),
+        "string" => {
+            let string = value.as_str().ok_or_else(|| mismatch(path, field_type, value))?;
+            AvroValue::String(string.to_owned())
+        }
+        "bytes" => AvroValue::Bytes(string_to_bytes(value, path)?),
+        integer if INTEGER_TYPES.contains(&integer) => {
+            AvroValue::Long(value.as_i64().ok_or_else(|| mismatch(path, field_type, value))?)
+        }
+        "null" => return Err(mismatch(path, field_type, value)),
+        name => avro_record_value(schema, record_for(schema, name, path)?, value, path)?,
+    };
+    Ok(avro_value)
+}
+
+fn proto_wire_type(field_type: &str) -> u8 {
+    match field_type {
+        "fixed32" | "sfixed32" | "float" => WIRE_32_BIT,
+        "fixed64" | "sfixed64" | "double" | "number" => WIRE_64_BIT,
+        "boolean" | "bool" => WIRE_VARINT,
+        integer if INTEGER_TYPES.contains(&integer) => WIRE_VARINT,
+        _ => WIRE_LEN,
+    }
+}
+
+/// Default of a field missing from a protobuf message
+fn proto_default(field_type: &str) -> Value {
+    match field_type {
+        "boolean" | "bool" => Value::Bool(false),
+        "float" | "double" | "number" => float(0.0),
+        "string" | "bytes" => Value::String(String::new()),
+        integer if INTEGER_TYPES.contains(&integer) => Value::from(0),
+        _ if field_type.ends_with("[]") => Value::Array(vec![]),
+        _ => Value::Null,
+    }
+}
+
+/// Protobuf field number of the field at `index` in its record
+fn proto_field_number(index: usize, field: &Field) -> u64 {
+    field.number.map_or(index as u64 + 1, u64::from)
+}
+
+fn check_field_numbers(record: &Record, path: &str) -> Result<(), SchemaError> {
+    let mut numbers = HashSet::new();
+    for (index, field) in record.fields.iter().enumerate() {
+        let number = proto_field_number(index, field);
+        if number == 0 || !numbers.insert(number) {
+            let field_path = format!("{}.{}", path, field.name);
+            return Err(SchemaError::new(&field_path, format!("invalid or reused field number {}", number)));
+        }
+    }
+    Ok(())
+}
+
+fn proto_decode_record(schema: &Schema, record: &Record, bytes: &[u8], path: &str) -> Result<Value, SchemaError> {
+    check_field_numbers(record, path)?;
+    let mut values: Vec<Option<Value>> = vec![None; record.fields.len()];
+    let mut input = Input::new(bytes);
+    while !input.is_empty() {
+        let key = input.varint(path)?;
+        let wire_type = (key & 0x7) as u8;
+        let field = record
+            .fields
+            .iter()
+            .enumerate()
+            .find(|(index, field)| proto_field_number(*index, field) == key >> 3);
+        let (index, field) = match field {
+            Some(field) => field,
+            None => {
+                proto_skip(wire_type, &mut input, path)?;
+                continue;
+            }
+        };
+        let field_path = format!("{}.{}", path, field.name);
+        let field_type = field.field_type.trim().trim_end_matches('?');
+        if let Some(item_type) = field_type.strip_suffix("[]") {
+            let item_type = item_type.trim_end_matches('?');
+            let mut items = match values[index].take() {
+                Some(Value::Array(items)) => items,
+                _ => vec![],
+            };
+            let item_wire_type = proto_wire_type(item_type);
+            if wire_type == WIRE_LEN && item_wire_type != WIRE_LEN {
+                // packed repeated scalars
+                let mut packed = Input::new(input.len_delimited(&field_path)?);
+                while !packed.is_empty() {
+                    let item_path = format!("{}[{}]", field_path, items.len());
+                    items.push(proto_decode(schema, item_type, item_wire_type, &mut packed, &item_path)?);
+                }
+            } else {
+                let item_path = format!("{}[{}]", field_path, items.len());
+                items.push(proto_decode(schema, item_type, wire_type, &mut input, &item_path)?);
+            }
+            values[index] = Some(Value::Array(items));
+        } else {
+            values[index] = Some(proto_decode(schema, field_type, wire_type, &mut input, &field_path)?);
+        }
+    }
+    let object = record
+        .fields
+        .iter()
+        .zip(values)
+        .map(|(field, value)| {
+            let field_type = field.field_type.trim();
+            let value = match value {
+                Some(value) => value,
+                None if field_type.ends_with('?') => Value::Null,
+                None => proto_default(field_type),
+            };
+            (field.name.clone(), value)
+        })
+        .collect::<Map<String, Value>>();
+    Ok(Value::Object(object))
+}
+
+fn proto_skip(wire_type: u8, input: &mut Input, path: &str) -> Result<(), SchemaError> {
+    match wire_type {
+        WIRE_VARINT => input.varint(path).map(|_| ()),
+        WIRE_64_BIT => input.take(8, path).map(|_| ()),
+        WIRE_LEN => input.len_delimited(path).map(|_| ()),
+        WIRE_32_BIT => input.take(4, path).map(|_| ()),
+        _ => Err(SchemaError::new(path, format!("unsupported wire type {}", wire_type))),
+    }
+}
+
+fn proto_decode(
+    schema: &Schema,
+    field_type: &str,
+    wire_type: u8,
+    input: &mut Input,
+    path: &str,
+) -> Result<Value, SchemaError> {
+    if wire_type != proto_wire_type(field_type) {
+        return Err(SchemaError::new(path, format!("wire type {} does not match {}", wire_type, field_type)));
+    }
+    match field_type {
+        "boolean" | "bool" => Ok(Value::Bool(input.varint(path)? != 0)),
+        "int" | "int32" => Ok(Value::from(in_range::<i32, _>(input.varint(path)? as i64, field_type, path)?)),
+        "uint32" => Ok(Value::from(in_range::<u32, _>(input.varint(path)?, field_type, path)?)),
+        "uint64" => Ok(Value::from(input.varint(path)?)),
+        "sint32" => Ok(Value::from(in_range::<i32, _>(unzigzag(input.varint(path)?), field_type, path)?)),
+        "sint64" => Ok(Value::from(unzigzag(input.varint(path)?))),
+        "fixed32" => Ok(Value::from(u32::from_le_bytes(input.array(path)?))),
+        "sfixed32" => Ok(Value::from(i32::from_le_bytes(input.array(path)?))),
+        "float" => Ok(float(f32::from_le_bytes(input.array(path)?) as f64)),
+        "fixed64" => Ok(Value::from(u64::from_le_bytes(input.array(path)?))),
+        "sfixed64" => Ok(Value::from(i64::from_le_bytes(input.array(path)?))),
+        "double" | "number" => Ok(float(f64::from_le_bytes(input.array(path)?))),
+        "string" => utf8(input.len_delimited(path)?, path),
+        "bytes" => Ok(bytes_to_string(input.len_delimited(path)?)),
+        integer if INTEGER_TYPES.contains(&integer) => Ok(Value::from(input.varint(path)? as i64)),
+        name => {
+            let record = record_for(schema, name, path)?;
+            proto_decode_record(schema, record, input.len_delimited(path)?, path)
+        }
+    }
+}
+
+fn proto_encode_record(
+    schema: &Schema,
+    record: &Record,
+    value: &Value,
+    output: &mut Vec<u8>,
+    path: &str,
+) -> Result<(), SchemaError> {
+    check_field_numbers(record, path)?;
+    let object = value.as_object().ok_or_else(|| mismatch(path, &record.name, value))?;
+    for (index, field) in record.fields.iter().enumerate() {
+        let number = proto_field_number(index, field);
+        let field_path = format!("{}.{}", path, field.name);
+        let field_value = object.get(&field.name).unwrap_or(&Value::Null);
+        let field_type = field.field_type.trim().trim_end_matches('?');
+        if field_value.is_null() {
+            continue;
+        }
+        if let Some(item_type) = field_type.strip_suffix("[]") {
+            let item_type = item_type.trim_end_matches('?');
+            let items = field_value.as_array().ok_or_else(|| mismatch(&field_path, "an array", field_value))?;
+            if proto_wire_type(item_type) == WIRE_LEN {
+                for (i, item) in items.iter().enumerate() {
+                    proto_encode(schema, number, item_type, item, output, &format!("{}[{}]", field_path, i))?;
+                }
+            } else if !items.is_empty() {
+                let mut packed = vec![];
+                for (i, item) in items.iter().enumerate() {
+                    proto_encode_payload(schema, item_type, item, &mut packed, &format!("{}[{}]", field_path, i))?;
+                }
+                write_varint(number << 3 | WIRE_LEN as u64, output);
+                write_varint(packed.len() as u64, output);
+                output.extend_from_slice(&packed);
+            }
+        } else {
+            proto_encode(schema, number, field_type, field_value, output, &field_path)?;
+        }
+    }
+    Ok(())
+}
+
+fn proto_encode(
+    schema: &Schema,
+    number: u64,
+    field_type: &str,
+    value: &Value,
+    output: &mut Vec<u8>,
+    path: &str,
+) -> Result<(), SchemaError> {
+    write_varint(number << 3 | proto_wire_type(field_type) as u64, output);
+    proto_encode_payload(schema, field_type, value, output, path)
+}
+
+fn proto_encode_payload(
+    schema: &Schema,
+    field_type: &str,
+    value: &Value,
+    output: &mut Vec<u8>,
+    path: &str,
+) -> Result<(), SchemaError> {
+    let signed = || value.as_i64().ok_or_else(|| mismatch(path, field_type, value));
+    let unsigned = || value.as_u64().ok_or_else(|| mismatch(path, field_type, value));
+    let number = || value.as_f64().ok_or_else(|| mismatch(path, field_type, value));
+    match field_type {
+        "boolean" | "bool" => {
+            let boolean = value.as_bool().ok_or_else(|| mismatch(path, field_type, value))?;
+            write_varint(boolean as u64, output);
+        }
+        "int" | "int32" => write_varint(in_range::<i32, _>(signed()?, field_type, path)? as i64 as u64, output),
+        "uint32" => write_varint(in_range::<u32, _>(unsigned()?, field_type, path)? as u64, output),
+        "uint64" => write_varint(unsigned()?, output),
+        "sint32" => write_varint(zigzag(in_range::<i32, _>(signed()?, field_type, path)? as i64), output),
+        "sint64" => write_varint(zigzag(signed()?), output),
+        "fixed32" => output.extend_from_slice(&in_range::<u32, _>(unsigned()?, field_type, path)?.to_le_bytes()),
+        "sfixed32" => output.extend_from_slice(&in_range::<i32, _>(signed()?, field_type, path)?.to_le_bytes()),
+        "float" => output.extend_from_slice(&(number()? as f32).to_le_bytes()),
+        "fixed64" => output.extend_from_slice(&unsigned()?.to_le_bytes()),
+        "sfixed64" => output.extend_from_slice(&signed()?.to_le_bytes()),
+        "double" | "number" => output.extend_from_slice(&number()?.to_le_bytes()),
+        "string" => {
+            let string = value.as_str().ok_or_else(|| mismatch(path, field_type, value))?;
+            write_varint(string.len() as u64, output);
+            output.extend_from_slice(string.as_bytes());
+        }
+        "bytes" => {
+            let bytes = string_to_bytes(value, path)?;
+            write_varint(bytes.len() as u64, output);
+            output.extend_from_slice(&bytes);
+        }
+        integer if INTEGER_TYPES.contains(&integer) => write_varint(signed()? as u64, output),
+        name => {
+            let record = record_for(schema, name, path)?;
+            let mut message = vec![];
+            proto_encode_record(schema, record, value, &mut message, path)?;
+            write_varint(message.len() as u64, output);
+            output.extend_from_slice(&message);
+        }
+    }
+    Ok(())
+}
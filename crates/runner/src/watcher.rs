@@ -22,6 +22,8 @@ pub struct DirectoryWatcher {
 }
 
 const ALTERNATE_STATES: [&str;5] = ["deploy", "undeploy", "running", "undeployed", "redeploy"];
+/// Bare module binaries and zip bundles
+const MODULE_EXTENSIONS: [&str;2] = ["wasm", "zip"];
 
 impl DirectoryWatcher {
     pub fn new(p: PathBuf) -> Self {
@@ -31,14 +33,19 @@ impl DirectoryWatcher {
     pub fn remove_next_states(&self) {
         for result in std::fs::read_dir(&self.dir).expect("read_dir fs") {
             let file = result.expect("DirEntry");
-            for state in ALTERNATE_STATES {
-                let path_buf = file.path();
-                let tentative_name = path_buf.file_stem().unwrap().to_str().unwrap().to_owned();
-                let state_path = self.dir.join(format!("{}.{}", tentative_name, state));
-                if state_path.exists() {
-                    if let Err(err) = fs::remove_file(state_path.clone()) {
-                        eprintln!("Cannot remove orphan state at {:?}", state_path);
-                    }
+            let path_buf = file.path();
+            let tentative_name = path_buf.file_stem().unwrap().to_str().unwrap().to_owned();
+            self.remove_states(&tentative_name);
+        }
+    }
+
+    /// Removes every pending state file (`name.deploy`, `name.redeploy`...) for a module
+    pub fn remove_states(&self, module_name: &str) {
+        for state in ALTERNATE_STATES {
+            let state_path = self.dir.join(format!("{}.{}", module_name, state));
+            if state_path.exists() {
+                if let Err(err) = fs::remove_file(state_path.clone()) {
+                    eprintln!("Cannot remove orphan state at {:?} because {:?}", state_path, err);
                 }
             }
         }
@@ -51,7 +58,7 @@ impl DirectoryWatcher {
             let file = result.expect("result needed");
             let path = file.path();
             let ext = path.extension();
-            if ext.is_some() && ext.unwrap().eq("wasm") {
+            if ext.is_some() && MODULE_EXTENSIONS.iter().any(|module_ext| ext.unwrap().eq(*module_ext)) {
                 let name = path.file_stem();
                 let mut status_str = "deploy";
                 for alternate_status in ALTERNATE_STATES {
@@ -0,0 +1,82 @@
+pub mod common;
+
+use wasm_central_runner::data::{ChunkReader, ChunkWriter, DataFrame};
+use wasm_central_runner::functions::FunctionManager;
+use wasm_central_runner::manifest::{Manifest, ManifestError};
+use wasm_central_runner::runner::ExecutionError;
+
+use std::fs;
+use std::io::{Cursor, Write};
+use std::time::Duration;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+fn bundle(manifest: &str) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(vec![]));
+    let files = [
+        ("echo.wasm", common::ECHO_WAT),
+        ("manifest.json", manifest),
+        ("assets/greeting.txt", "hello"),
+    ];
+    for (name, contents) in files {
+        writer.start_file(name, FileOptions::default()).unwrap();
+        writer.write_all(contents.as_bytes()).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+#[test]
+fn test_bundle_deploy() {
+    let manifest = r#"{"name": "echo", "version": "1.0.0", "entrypoints": ["main"], "limits": {"timeout_ms": 500}}"#;
+    let (module_manager, handle) = common::deploy("runtime-bundle", &[("echo.zip", bundle(manifest))]);
+    let rt_path = common::runtime_path("runtime-bundle");
+    assert_eq!(1, module_manager.running_modules().len());
+
+    let module = module_manager.running_modules_map().remove("echo").unwrap();
+    assert_eq!(Some("1.0.0".to_string()), module.manifest.unwrap().version);
+    assert_eq!(Some(Duration::from_millis(500)), module_manager.limits_for("echo").timeout);
+    let asset = fs::read_to_string(rt_path.join(".assets/echo/1.0.0/greeting.txt")).unwrap();
+    assert_eq!("hello", asset);
+
+    let mut frame = DataFrame::new(b"ping".to_vec());
+    frame.metadata.entrypoint = Some("main".to_string());
+    assert_eq!(b"ping".to_vec(), handle.run(&frame).result.unwrap().body);
+
+    frame.metadata.entrypoint = Some("other".to_string());
+    assert!(matches!(handle.run(&frame).result, Err(ExecutionError::InvalidInput(_))));
+
+    // streamed calls go through the same allow-list
+    let (_input_sender, input) = ChunkReader::channel(1);
+    let invocation = handle.run_stream(&frame.metadata, input, ChunkWriter::new(|_| Ok(())));
+    assert!(matches!(invocation.result, Err(ExecutionError::InvalidInput(_))));
+
+    module_manager.unload("echo").expect("Cannot unload echo");
+    assert!(!rt_path.join(".assets/echo").exists());
+}
+
+#[test]
+fn test_bundle_manifest_mismatch() {
+    let rt_path = common::runtime_dir("runtime-bundle-mismatch");
+    fs::write(rt_path.join("echo.zip"), bundle(r#"{"name": "other"}"#)).expect("Cannot write bundle");
+
+    let module_manager = FunctionManager::new(rt_path.clone());
+    module_manager.tick();
+    assert!(module_manager.get_handle(&"echo".to_string()).is_none());
+}
+
+#[test]
+fn test_manifest_validation() {
+    let manifest = Manifest::from_json(br#"{"capabilities": ["env", "network"]}"#).unwrap();
+    assert!(matches!(
+        manifest.validate("echo"),
+        Err(ManifestError::UnsupportedCapability(capability)) if capability == "network"
+    ));
+
+    let manifest = Manifest::from_json(br#"{"entrypoints": ["main", ""]}"#).unwrap();
+    assert!(matches!(manifest.validate("echo"), Err(ManifestError::InvalidEntrypoint(_))));
+
+    assert!(matches!(
+        Manifest::from_json(br#"{"limits": {"fuel": -1}}"#),
+        Err(ManifestError::Parse(_))
+    ));
+}
@@ -0,0 +1,234 @@
+use wasm_central_runner::functions::{FunctionManager, ModuleHandle};
+use wasm_central_runner::schema::{Field, Record, Schema, SchemaFormat};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Guest spinning forever in its `_start`
+pub const LOOP_WAT: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (loop $spin (br $spin))))
+"#;
+
+/// Module without a default export, failing every run before the guest starts and never worth a
+/// retry
+pub const NO_START_WAT: &str = r#"
+(module
+  (memory (export "memory") 1))
+"#;
+
+/// Guest counting to 25000 in its `_start`, burning between 150000 and 200000 units of fuel
+pub const COUNT_WAT: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (local $i i32)
+    (loop $count
+      (br_if $count (i32.lt_u (local.tee $i (i32.add (local.get $i) (i32.const 1))) (i32.const 25000))))))
+"#;
+
+/// Guest growing its memory one page at a time, trapping once a growth is denied
+pub const GROW_WAT: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (loop $grow
+      (br_if $grow (i32.ne (memory.grow (i32.const 1)) (i32.const -1))))
+    unreachable))
+"#;
+
+/// WASI command growing its memory until a growth is denied, then exiting with code 3
+pub const GROW_EXIT_WAT: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "proc_exit"
+    (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (loop $grow
+      (br_if $grow (i32.ne (memory.grow (i32.const 1)) (i32.const -1))))
+    (call $proc_exit (i32.const 3))))
+"#;
+
+/// WASI command copying its stdin to its stdout
+pub const ECHO_WAT: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "fd_read"
+    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 64))
+    (i32.store (i32.const 4) (i32.const 4096))
+    (loop $copy
+      (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 16)))
+      (if (i32.gt_u (i32.load (i32.const 16)) (i32.const 0))
+        (then
+          (i32.store (i32.const 8) (i32.const 64))
+          (i32.store (i32.const 12) (i32.load (i32.const 16)))
+          (drop (call $fd_write (i32.const 1) (i32.const 8) (i32.const 1) (i32.const 20)))
+          (br $copy))))))
+"#;
+
+/// WASI command writing `oops` to its stderr
+pub const STDERR_WAT: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 64) "oops\n")
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 64))
+    (i32.store (i32.const 4) (i32.const 5))
+    (drop (call $fd_write (i32.const 2) (i32.const 0) (i32.const 1) (i32.const 16)))))
+"#;
+
+/// Guest trapping right away
+pub const TRAP_WAT: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func $fail unreachable)
+  (func (export "_start")
+    (call $fail)))
+"#;
+
+/// WASI command exiting with code 3
+pub const EXIT_WAT: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "proc_exit"
+    (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (call $proc_exit (i32.const 3))))
+"#;
+
+/// WASI command writing `done` to its stdout then exiting with code 0
+pub const CLEAN_EXIT_WAT: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit"
+    (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 64) "done")
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 64))
+    (i32.store (i32.const 4) (i32.const 4))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 16)))
+    (call $proc_exit (i32.const 0))
+    unreachable))
+"#;
+
+/// WASI command writing its environment to its stdout, as `NAME=value` nul terminated entries
+pub const ENV_WAT: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "environ_sizes_get"
+    (func $environ_sizes_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "environ_get"
+    (func $environ_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (drop (call $environ_sizes_get (i32.const 0) (i32.const 4)))
+    (drop (call $environ_get (i32.const 64) (i32.const 1024)))
+    (i32.store (i32.const 8) (i32.const 1024))
+    (i32.store (i32.const 12) (i32.load (i32.const 4)))
+    (drop (call $fd_write (i32.const 1) (i32.const 8) (i32.const 1) (i32.const 16)))))
+"#;
+
+/// WASI command writing the same JSON document to its stdout whatever its input
+pub const JSON_WAT: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 64) "{\"id\":1,\"tags\":[\"b\"],\"extra\":true}")
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 64))
+    (i32.store (i32.const 4) (i32.const 34))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 16)))))
+"#;
+
+/// Directory of the modules of a single test, `./target/<dir>`
+pub fn runtime_path(dir: &str) -> PathBuf {
+    PathBuf::from("./target").join(dir)
+}
+
+/// Empties the directory of the modules of a single test
+pub fn runtime_dir(dir: &str) -> PathBuf {
+    let rt_path = runtime_path(dir);
+    let _ = fs::remove_dir_all(&rt_path);
+    fs::create_dir_all(&rt_path).expect("Cannot create directory for runtime modules");
+    rt_path
+}
+
+/// Writes `files` to an emptied `./target/<dir>` and deploys them, returning the manager and a
+/// handle on the first module. Names without an extension get `.wasm` appended, so modules can
+/// be given by name and sidecar manifests or bundles by file name
+pub fn deploy<C: AsRef<[u8]>>(dir: &str, files: &[(&str, C)]) -> (FunctionManager, ModuleHandle) {
+    deploy_with(dir, files, FunctionManager::new)
+}
+
+/// Same as `deploy` with the manager `new_manager` creates over the directory
+pub fn deploy_with<C: AsRef<[u8]>>(
+    dir: &str,
+    files: &[(&str, C)],
+    new_manager: impl FnOnce(PathBuf) -> FunctionManager,
+) -> (FunctionManager, ModuleHandle) {
+    let rt_path = runtime_dir(dir);
+    for (name, contents) in files {
+        let file_name = if name.contains('.') {
+            name.to_string()
+        } else {
+            format!("{}.wasm", name)
+        };
+        fs::write(rt_path.join(file_name), contents).expect("Cannot write test module");
+    }
+    let module_manager = new_manager(rt_path);
+    module_manager.tick();
+    let module_name = files[0].0.split('.').next().unwrap().to_string();
+    let handle = module_manager
+        .get_handle(&module_name)
+        .unwrap_or_else(|| panic!("Module {} is not deployed", module_name));
+    (module_manager, handle)
+}
+
+/// Deploys `wat` as version `version` of the echo module, replacing the previous one on disk
+pub fn write_version(rt_path: &Path, wat: &str, version: &str) {
+    fs::write(rt_path.join("echo.wasm"), wat).expect("Cannot write echo module");
+    fs::write(rt_path.join("echo.manifest.json"), format!(r#"{{"version": "{}"}}"#, version))
+        .expect("Cannot write echo manifest");
+}
+
+pub fn field(name: &str, field_type: &str) -> Field {
+    Field {
+        name: name.to_string(),
+        field_type: field_type.to_string(),
+        number: None,
+    }
+}
+
+/// Order made of `Line` records, with a scalar array and optional fields of every kind
+pub fn order_schema(format: SchemaFormat) -> Schema {
+    Schema {
+        format,
+        records: vec![
+            Record {
+                name: "Order".to_string(),
+                fields: vec![
+                    field("id", "long"),
+                    field("note", "string?"),
+                    field("lines", "Line[]"),
+                    field("weights", "double[]?"),
+                ],
+            },
+            Record {
+                name: "Line".to_string(),
+                fields: vec![field("sku", "string"), field("quantity", "sint32"), field("tag", "bytes?")],
+            },
+        ],
+    }
+}
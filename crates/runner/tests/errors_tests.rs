@@ -0,0 +1,58 @@
+pub mod common;
+
+use wasm_central_runner::data::{DataFrame, StderrCapture};
+use wasm_central_runner::functions::FunctionManager;
+use wasm_central_runner::limits::ExecutionLimits;
+use wasm_central_runner::runner::{new_pair, ExecutionError};
+
+use std::path::Path;
+use wasmtime::TrapCode;
+
+#[test]
+fn test_trap() {
+    let (compiler, executor) = new_pair();
+    let compilation_unit = compiler
+        .compile(&mut common::TRAP_WAT.as_bytes())
+        .expect("Cannot compile trapping module");
+
+    let result = executor.execute(&Some(compilation_unit), &ExecutionLimits::default(), DataFrame::new(vec![]), &StderrCapture::new(1024));
+    match result {
+        Err(ExecutionError::Trap { trap_code, backtrace, .. }) => {
+            assert_eq!(trap_code, Some(TrapCode::UnreachableCodeReached));
+            assert!(backtrace.iter().any(|frame| frame.ends_with("!fail")));
+        }
+        _ => panic!("Expected a trap"),
+    }
+}
+
+#[test]
+fn test_non_zero_exit() {
+    let (compiler, executor) = new_pair();
+    let compilation_unit = compiler
+        .compile(&mut common::EXIT_WAT.as_bytes())
+        .expect("Cannot compile exiting module");
+
+    let result = executor.execute(&Some(compilation_unit), &ExecutionLimits::default(), DataFrame::new(vec![]), &StderrCapture::new(1024));
+    assert!(matches!(result, Err(ExecutionError::NonZeroExit(3))));
+}
+
+#[test]
+fn test_unknown_module_handle() {
+    let path = Path::new("target/runtime-errors");
+    std::fs::create_dir_all(path).expect("Cannot create runtime dir");
+    let module_manager = FunctionManager::new(path.to_path_buf());
+
+    let result = module_manager.handle("missing");
+    assert!(matches!(result, Err(ExecutionError::ModuleNotFound(_))));
+}
+
+#[test]
+fn test_clean_exit() {
+    let (compiler, executor) = new_pair();
+    let compilation_unit = compiler
+        .compile(&mut common::CLEAN_EXIT_WAT.as_bytes())
+        .expect("Cannot compile exiting module");
+
+    let result = executor.execute(&Some(compilation_unit), &ExecutionLimits::default(), DataFrame::new(vec![]), &StderrCapture::new(1024));
+    assert_eq!(result.expect("Clean exit is a success").body, b"done".to_vec());
+}
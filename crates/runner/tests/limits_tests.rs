@@ -0,0 +1,108 @@
+pub mod common;
+
+use wasm_central_runner::data::{DataFrame, StderrCapture};
+use wasm_central_runner::limits::ExecutionLimits;
+use wasm_central_runner::runner::{new_async_pair, new_pair, ExecutionError};
+
+use std::time::Duration;
+
+#[test]
+fn test_fuel_exhaustion() {
+    let (compiler, executor) = new_pair();
+    let compilation_unit = compiler
+        .compile(&mut common::LOOP_WAT.as_bytes())
+        .expect("Cannot compile looping module");
+
+    let limits = ExecutionLimits {
+        fuel: Some(10_000),
+        ..ExecutionLimits::default()
+    };
+    let result = executor.execute(&Some(compilation_unit), &limits, DataFrame::new(vec![]), &StderrCapture::new(1024));
+    assert!(matches!(result, Err(ExecutionError::FuelExhausted(10_000))));
+}
+
+#[tokio::test]
+async fn test_async_fuel_budget() {
+    let (compiler, executor) = new_async_pair();
+    let compilation_unit = Some(
+        compiler
+            .compile(&mut common::COUNT_WAT.as_bytes())
+            .expect("Cannot compile counting module"),
+    );
+
+    // not a multiple of the fuel handed over between yields
+    let limits = ExecutionLimits {
+        fuel: Some(130_000),
+        ..ExecutionLimits::default()
+    };
+    let result = executor
+        .execute_async(&compilation_unit, &limits, DataFrame::new(vec![]), &StderrCapture::new(1024))
+        .await;
+    assert!(matches!(result, Err(ExecutionError::FuelExhausted(130_000))));
+
+    let limits = ExecutionLimits {
+        fuel: Some(1_000_000),
+        ..ExecutionLimits::default()
+    };
+    let result = executor
+        .execute_async(&compilation_unit, &limits, DataFrame::new(vec![]), &StderrCapture::new(1024))
+        .await;
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_timeout() {
+    let (compiler, executor) = new_pair();
+    let compilation_unit = compiler
+        .compile(&mut common::LOOP_WAT.as_bytes())
+        .expect("Cannot compile looping module");
+
+    let limits = ExecutionLimits {
+        timeout: Some(Duration::from_millis(50)),
+        ..ExecutionLimits::default()
+    };
+    let result = executor.execute(&Some(compilation_unit), &limits, DataFrame::new(vec![]), &StderrCapture::new(1024));
+    assert!(matches!(result, Err(ExecutionError::Timeout(_))));
+}
+
+#[test]
+fn test_limits_merge() {
+    let defaults = ExecutionLimits {
+        fuel: Some(1_000),
+        timeout: Some(Duration::from_secs(1)),
+        max_memory_bytes: Some(1 << 20),
+        ..ExecutionLimits::default()
+    };
+    let overrides = ExecutionLimits {
+        fuel: Some(5_000),
+        max_memory_bytes: Some(1 << 24),
+        ..ExecutionLimits::default()
+    };
+    let merged = defaults.merge(&overrides);
+    assert_eq!(Some(5_000), merged.fuel);
+    assert_eq!(Some(Duration::from_secs(1)), merged.timeout);
+    assert_eq!(Some(1 << 24), merged.max_memory_bytes);
+    assert_eq!(None, merged.max_instances);
+}
+
+#[test]
+fn test_memory_limit() {
+    let (compiler, executor) = new_pair();
+    let compilation_unit = compiler
+        .compile(&mut common::GROW_WAT.as_bytes())
+        .expect("Cannot compile memory growing module");
+
+    let limits = ExecutionLimits {
+        max_memory_bytes: Some(4 * 65536),
+        ..ExecutionLimits::default()
+    };
+    let result = executor.execute(&Some(compilation_unit), &limits, DataFrame::new(vec![]), &StderrCapture::new(1024));
+    assert!(matches!(result, Err(ExecutionError::MemoryLimitExceeded(262144))));
+
+    // the guest got over the denied growth, its exit status is what counts
+    let compilation_unit = compiler
+        .compile(&mut common::GROW_EXIT_WAT.as_bytes())
+        .expect("Cannot compile memory growing module");
+    let result = executor.execute(&Some(compilation_unit), &limits, DataFrame::new(vec![]), &StderrCapture::new(1024));
+    assert!(matches!(result, Err(ExecutionError::NonZeroExit(3))));
+}
@@ -0,0 +1,136 @@
+pub mod common;
+
+use wasm_central_runner::data::DataFrame;
+use wasm_central_runner::functions::FunctionManager;
+use wasm_central_runner::limits::ExecutionLimits;
+use wasm_central_runner::manifest::{Manifest, ManifestError};
+use wasm_central_runner::runner::ExecutionError;
+
+use std::time::{Duration, Instant, SystemTime};
+
+/// Retried twice 50ms apart, every attempt running out of instances
+const BUSY_MANIFEST: &str = r#"{"limits": {"max_instances": 0}, "retry": {"max_attempts": 3, "backoff_ms": 50}}"#;
+
+#[test]
+fn test_sidecar_manifest() {
+    let (module_manager, handle) = common::deploy(
+        "runtime-manifest",
+        &[
+            ("env", common::ENV_WAT),
+            (
+                "env.manifest.toml",
+                r#"
+display_name = "Environment"
+version = "2.1.0"
+owner = "platform"
+capabilities = ["env"]
+
+[env]
+GREETING = "hello"
+"#,
+            ),
+        ],
+    );
+
+    let module = module_manager.running_modules_map().remove("env").unwrap();
+    let manifest = module.manifest.expect("Sidecar manifest was not read");
+    assert_eq!(Some("Environment".to_string()), manifest.display_name);
+    assert_eq!(Some("platform".to_string()), manifest.owner);
+
+    let output = handle.run(&DataFrame::new(vec![])).result.expect("Cannot run env module");
+    let env = String::from_utf8(output.body).expect("Env is not utf-8");
+    assert!(env.split('\0').any(|var| var == "GREETING=hello"));
+}
+
+#[test]
+fn test_manifest_retry() {
+    let (module_manager, handle) = common::deploy(
+        "runtime-manifest-retry",
+        &[
+            ("loop", common::LOOP_WAT),
+            ("loop.manifest.json", r#"{"timeout_ms": 50, "retry": {"max_attempts": 5}}"#),
+            ("nostart", common::NO_START_WAT),
+            ("nostart.manifest.json", r#"{"retry": {"max_attempts": 3, "backoff_ms": 50}}"#),
+            ("busy", common::ECHO_WAT),
+            ("busy.manifest.json", BUSY_MANIFEST),
+        ],
+    );
+    assert_eq!(Some(Duration::from_millis(50)), module_manager.limits_for("loop").timeout);
+
+    // timeouts are not retried
+    let t_start = Instant::now();
+    let invocation = handle.run(&DataFrame::new(vec![]));
+    assert!(matches!(invocation.result, Err(ExecutionError::Timeout(_))));
+    assert!(t_start.elapsed() < Duration::from_millis(200));
+
+    // neither are modules that cannot run
+    let handle = module_manager
+        .get_handle(&"nostart".to_string())
+        .expect("Nostart module is not deployed");
+    let t_start = Instant::now();
+    let invocation = handle.run(&DataFrame::new(vec![]));
+    assert!(matches!(invocation.result, Err(ExecutionError::InvalidModule(_))));
+    assert!(t_start.elapsed() < Duration::from_millis(50));
+
+    // running out of instances is, twice backing off
+    let handle = module_manager
+        .get_handle(&"busy".to_string())
+        .expect("Busy module is not deployed");
+    let t_start = Instant::now();
+    let invocation = handle.run(&DataFrame::new(vec![]));
+    assert!(matches!(invocation.result, Err(ExecutionError::Failure(_))));
+    assert!(t_start.elapsed() >= Duration::from_millis(100));
+
+    // no attempt past the deadline
+    let mut frame = DataFrame::new(vec![]);
+    frame.metadata.deadline = Some(SystemTime::now());
+    let t_start = Instant::now();
+    let invocation = handle.run(&frame);
+    assert!(matches!(invocation.result, Err(ExecutionError::Failure(_))));
+    assert!(t_start.elapsed() < Duration::from_millis(50));
+}
+
+#[tokio::test]
+async fn test_manifest_retry_async() {
+    let (_module_manager, handle) = common::deploy_with(
+        "runtime-manifest-retry-async",
+        &[("busy", common::ECHO_WAT), ("busy.manifest.json", BUSY_MANIFEST)],
+        |rt_path| FunctionManager::new_async(rt_path, ExecutionLimits::default()),
+    );
+    let t_start = Instant::now();
+    let invocation = handle.run_async(&DataFrame::new(vec![])).await;
+    assert!(matches!(invocation.result, Err(ExecutionError::Failure(_))));
+    assert!(t_start.elapsed() >= Duration::from_millis(100));
+}
+
+#[test]
+fn test_manifest_config() {
+    let manifest = Manifest::from_toml(
+        br#"
+timeout_ms = 200
+capabilities = ["assets"]
+
+[limits]
+fuel = 10
+timeout_ms = 900
+
+[env]
+GREETING = "hello"
+"#,
+    )
+    .unwrap();
+    assert_eq!(Some(Duration::from_millis(200)), manifest.limits().timeout);
+    assert_eq!(Some(10), manifest.limits().fuel);
+    assert!(!manifest.allows("env"));
+    assert!(matches!(manifest.validate("echo"), Err(ManifestError::MissingCapability("env"))));
+
+    let manifest = Manifest::from_json(br#"{"env": {"WASM_CENTRAL_SENDER": "billing"}}"#).unwrap();
+    assert!(matches!(manifest.validate("echo"), Err(ManifestError::InvalidEnv(_))));
+
+    // granted to every module, so there is nothing to ask for
+    let manifest = Manifest::from_json(br#"{"capabilities": ["clocks"]}"#).unwrap();
+    assert!(matches!(manifest.validate("echo"), Err(ManifestError::UnsupportedCapability(_))));
+
+    let manifest = Manifest::from_json(br#"{"retry": {"max_attempts": 0}}"#).unwrap();
+    assert!(matches!(manifest.validate("echo"), Err(ManifestError::InvalidRetry)));
+}
@@ -0,0 +1,25 @@
+use wasm_central_runner::metrics::ModuleMetrics;
+
+use std::time::Duration;
+
+#[test]
+fn test_metrics_counters() {
+    let metrics = ModuleMetrics::new();
+    for i in 1..=10 {
+        metrics.record_success(Duration::from_millis(i));
+    }
+    metrics.record_failure(Duration::from_millis(100));
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(10, snapshot.successes);
+    assert_eq!(1, snapshot.failures);
+    assert_eq!(11, snapshot.total_messages);
+    assert_eq!(1.0, snapshot.fail_rate_per_minute);
+    assert_eq!(6.0, snapshot.latency.p50_ms);
+    assert_eq!(100.0, snapshot.latency.p99_ms);
+
+    metrics.reset();
+    let snapshot = metrics.snapshot();
+    assert_eq!(0, snapshot.total_messages);
+    assert_eq!(0.0, snapshot.fail_rate_per_minute);
+}
@@ -1,4 +1,6 @@
-use wasm_central_runner::functions::FunctionManager;
+pub mod common;
+
+use wasm_central_runner::functions::{FunctionManager, FunctionManagerError};
 
 use std::fs;
 use std::path::PathBuf;
@@ -13,7 +15,7 @@ fn test_file_handling() -> Result<(), std::io::Error> {
     fs::remove_dir_all(rt_path.clone())?;
     fs::create_dir(rt_path.clone())?;
 
-    let mut module_manager = FunctionManager::new(rt_path.clone());
+    let module_manager = FunctionManager::new(rt_path.clone());
 
     module_manager.tick();
     assert_eq!(0, module_manager.running_modules().len());
@@ -39,3 +41,24 @@ fn test_file_handling() -> Result<(), std::io::Error> {
     module_manager.tick();
     Ok(())
 }
+
+#[test]
+fn test_unload() -> Result<(), std::io::Error> {
+    let (module_manager, _) = common::deploy(
+        "runtime-unload",
+        &[("echo", common::ECHO_WAT), ("echo.manifest.toml", r#"version = "1.0.0""#)],
+    );
+    let rt_path = common::runtime_path("runtime-unload");
+
+    let result = module_manager.unload("unknown");
+    assert!(matches!(result, Err(FunctionManagerError::UnavailableModule(_))));
+
+    // the sidecar goes along with the binary, so it does not apply to the next upload
+    assert_eq!(rt_path.join("echo.wasm"), module_manager.unload("echo").expect("Cannot unload echo"));
+    assert!(!rt_path.join("echo.wasm").exists());
+    assert!(!rt_path.join("echo.manifest.toml").exists());
+    fs::write(rt_path.join("echo.wasm"), common::ECHO_WAT)?;
+    module_manager.tick();
+    assert_ne!("1.0.0", module_manager.versions("echo")[0].version);
+    Ok(())
+}
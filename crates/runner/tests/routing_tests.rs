@@ -0,0 +1,77 @@
+pub mod common;
+
+use wasm_central_runner::data::DataFrame;
+use wasm_central_runner::functions::{FunctionManager, FunctionManagerError};
+use wasm_central_runner::routing::{SplitMode, TrafficSplit};
+
+fn deploy_two_versions(dir: &str) -> FunctionManager {
+    let (module_manager, _) = common::deploy(
+        dir,
+        &[("echo", common::ENV_WAT), ("echo.manifest.json", r#"{"version": "1.0.0"}"#)],
+    );
+    common::write_version(&common::runtime_path(dir), common::ECHO_WAT, "2.0.0");
+    module_manager.tick();
+    module_manager.rollback("echo").expect("Cannot roll back echo");
+    module_manager
+}
+
+#[test]
+fn test_split_sampling() {
+    for percent in [1, 10, 33, 50, 100] {
+        let split = TrafficSplit::new("2.0.0".to_string(), percent, SplitMode::Canary);
+        let picked = (0..200).filter(|_| split.sample()).count();
+        assert_eq!(2 * percent as usize, picked);
+    }
+}
+
+#[test]
+fn test_canary_routing() {
+    let module_manager = deploy_two_versions("runtime-canary");
+
+    module_manager
+        .set_traffic_split("echo", TrafficSplit::new("2.0.0".to_string(), 25, SplitMode::Canary))
+        .expect("Cannot split echo traffic");
+    let frame = DataFrame::new(b"ping".to_vec());
+    let echoed = (0..100)
+        .map(|_| module_manager.route("echo").unwrap())
+        .filter(|route| {
+            assert!(route.mirror.is_none());
+            route.handle.run(&frame).result.unwrap().body == b"ping"
+        })
+        .count();
+    assert_eq!(25, echoed);
+
+    let versions = module_manager.versions("echo");
+    assert_eq!(75, versions[0].metrics.snapshot().successes);
+    assert_eq!(25, versions[1].metrics.snapshot().successes);
+
+    // calls naming a version are never split
+    let route = module_manager.route("echo@1.0.0").unwrap();
+    assert_eq!("1.0.0", route.handle.version);
+}
+
+#[test]
+fn test_mirror_routing() {
+    let module_manager = deploy_two_versions("runtime-mirror");
+
+    module_manager
+        .set_traffic_split("echo", TrafficSplit::new("2.0.0".to_string(), 100, SplitMode::Mirror))
+        .expect("Cannot split echo traffic");
+    let route = module_manager.route("echo").unwrap();
+    assert_eq!("1.0.0", route.handle.version);
+    assert_eq!("2.0.0", route.mirror.unwrap().version);
+
+    module_manager.clear_traffic_split("echo");
+    assert!(module_manager.route("echo").unwrap().mirror.is_none());
+}
+
+#[test]
+fn test_invalid_split() {
+    let module_manager = deploy_two_versions("runtime-split");
+
+    let result = module_manager.set_traffic_split("echo", TrafficSplit::new("3.0.0".to_string(), 10, SplitMode::Canary));
+    assert!(matches!(result, Err(FunctionManagerError::UnavailableModule(_))));
+    let result = module_manager.set_traffic_split("echo", TrafficSplit::new("2.0.0".to_string(), 0, SplitMode::Canary));
+    assert!(matches!(result, Err(FunctionManagerError::InvalidTrafficSplit(_, _))));
+    assert!(module_manager.traffic_split("echo").is_none());
+}
@@ -1,7 +1,20 @@
+pub mod common;
+
+use wasm_central_runner::data::{ChunkReader, ChunkWriter, DataFrame, Metadata, StderrCapture};
 use wasm_central_runner::functions::FunctionManager;
+use wasm_central_runner::limits::ExecutionLimits;
+use wasm_central_runner::runner::{engine_config, new_async_pair, new_pair, ExecutionError};
 
 use std::fs;
+use std::io;
+use std::io::{Read, Write};
 use std::path::PathBuf;
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::{Duration, Instant};
+use wasi_cap_std_sync::WasiCtxBuilder;
+use wasi_common::pipe::{ReadPipe, WritePipe};
+use wasmtime::{Engine, Linker, Module, Store};
 
 #[test]
 fn test_executor_basics() {
@@ -13,7 +26,7 @@ fn test_executor_basics() {
     let _ = fs::remove_dir_all(rt_path.clone());
     fs::create_dir(rt_path.clone()).expect("Cannot create directory for runtime modules");
 
-    let mut module_manager = FunctionManager::new(rt_path.clone());
+    let module_manager = FunctionManager::new(rt_path.clone());
 
     let module_path = rt_path.join("./module.zip");
     fs::copy(full_path.clone(), module_path.clone())
@@ -27,3 +40,235 @@ fn test_executor_basics() {
     assert_eq!(true, module_handle.is_some());
     assert_eq!(1, module_manager.running_modules().len());
 }
+
+#[test]
+fn test_executor_echo() {
+    let (compiler, executor) = new_pair();
+    let compilation_unit = compiler
+        .compile(&mut common::ECHO_WAT.as_bytes())
+        .expect("Cannot compile echo module");
+
+    let body = b"{\"a\": 1}".to_vec();
+    let output = executor
+        .execute(&Some(compilation_unit), &ExecutionLimits::default(), DataFrame::new(body.clone()), &StderrCapture::new(1024))
+        .expect("Cannot execute echo module");
+    assert_eq!(body, output.body);
+}
+
+#[test]
+fn test_executor_stream() {
+    let (compiler, executor) = new_pair();
+    let compilation_unit = Some(
+        compiler
+            .compile(&mut common::ECHO_WAT.as_bytes())
+            .expect("Cannot compile echo module"),
+    );
+
+    let (input_sender, input) = ChunkReader::channel(1);
+    let (output_sender, output_receiver) = channel();
+    let output = ChunkWriter::new(move |chunk| {
+        output_sender.send(chunk).unwrap();
+        Ok(())
+    });
+    let feeder = thread::spawn(move || {
+        for chunk in ["[1,", "2,", "3]"] {
+            input_sender.send(Ok(chunk.as_bytes().to_vec())).unwrap();
+        }
+    });
+    executor
+        .execute_stream(&compilation_unit, &ExecutionLimits::default(), &Metadata::default(), input, output, &StderrCapture::new(1024))
+        .expect("Cannot stream echo module");
+    feeder.join().expect("Feeder panicked");
+
+    let echoed = output_receiver.iter().flatten().collect::<Vec<u8>>();
+    assert_eq!(b"[1,2,3]".to_vec(), echoed);
+}
+
+#[test]
+fn test_chunk_reader_failures() {
+    let (input_sender, mut input) = ChunkReader::channel(2);
+    input_sender.send(Ok(b"ab".to_vec())).unwrap();
+    input_sender
+        .send(Err(io::Error::new(io::ErrorKind::ConnectionAborted, "client went away")))
+        .unwrap();
+    let mut buf = vec![];
+    let err = input.read_to_end(&mut buf).unwrap_err();
+    assert_eq!(io::ErrorKind::ConnectionAborted, err.kind());
+    assert_eq!(b"ab".to_vec(), buf);
+
+    // the sender stays alive but never sends
+    let (_input_sender, mut input) = ChunkReader::channel(1);
+    input.set_timeout(Duration::from_millis(50));
+    let err = input.read(&mut [0; 8]).unwrap_err();
+    assert_eq!(io::ErrorKind::TimedOut, err.kind());
+}
+
+#[tokio::test]
+async fn test_executor_async() {
+    let (compiler, executor) = new_async_pair();
+    let echo_unit = Some(
+        compiler
+            .compile(&mut common::ECHO_WAT.as_bytes())
+            .expect("Cannot compile echo module"),
+    );
+    let loop_unit = Some(
+        compiler
+            .compile(&mut common::LOOP_WAT.as_bytes())
+            .expect("Cannot compile looping module"),
+    );
+
+    let body = b"{\"a\": 1}".to_vec();
+    let limits = ExecutionLimits {
+        fuel: Some(1_000_000),
+        ..ExecutionLimits::default()
+    };
+    let stderr = StderrCapture::new(1024);
+    let (echoed, looped) = tokio::join!(
+        executor.execute_async(&echo_unit, &limits, DataFrame::new(body.clone()), &stderr),
+        executor.execute_async(&loop_unit, &limits, DataFrame::new(vec![]), &stderr)
+    );
+    assert_eq!(body, echoed.expect("Cannot execute echo module").body);
+    assert!(matches!(looped, Err(ExecutionError::FuelExhausted(1_000_000))));
+
+    let sync_result = executor.execute(&echo_unit, &limits, DataFrame::new(body), &stderr);
+    assert!(matches!(sync_result, Err(ExecutionError::Failure(_))));
+}
+
+#[test]
+fn test_concurrent_handles() {
+    let (module_manager, handle) = common::deploy("runtime-concurrent", &[("echo", common::ECHO_WAT)]);
+    let workers = (0..8)
+        .map(|i| {
+            let handle = handle.clone();
+            thread::spawn(move || {
+                let body = format!("{{\"worker\": {}}}", i).into_bytes();
+                let output = handle
+                    .run(&DataFrame::new(body.clone()))
+                    .result
+                    .expect("Cannot run echo");
+                assert_eq!(body, output.body);
+            })
+        })
+        .collect::<Vec<_>>();
+    for worker in workers {
+        worker.join().expect("Worker panicked");
+    }
+
+    let module = module_manager.running_modules().pop().expect("Echo module is not running");
+    assert_eq!(8, module.metrics.snapshot().successes);
+}
+
+#[test]
+fn test_batch() {
+    let (_module_manager, handle) = common::deploy("runtime-batch", &[("echo", common::ECHO_WAT)]);
+    let frames = (0..3)
+        .map(|i| DataFrame::new(format!("{{\"record\": {}}}", i).into_bytes()))
+        .collect::<Vec<DataFrame>>();
+    let invocations = handle.run_batch(&frames);
+    assert_eq!(3, invocations.len());
+    for (frame, invocation) in frames.iter().zip(invocations) {
+        assert_eq!(frame.body, invocation.result.expect("Cannot run echo").body);
+    }
+}
+
+#[test]
+fn test_stderr_capture() {
+    let log_path = common::runtime_path("runtime-stderr-logs");
+    let _ = fs::remove_dir_all(&log_path);
+    let (_module_manager, handle) = common::deploy_with("runtime-stderr", &[("noisy", common::STDERR_WAT)], |rt_path| {
+        FunctionManager::new(rt_path)
+            .with_guest_log(log_path.clone())
+            .expect("Cannot create guest log directory")
+    });
+
+    // the log directory is created up front, failing right away when it cannot be
+    let rt_path = common::runtime_path("runtime-stderr");
+    assert!(FunctionManager::new(rt_path.clone()).with_guest_log(rt_path.join("noisy.wasm")).is_err());
+
+    let invocation = handle.run(&DataFrame::new(vec![]));
+    assert!(invocation.result.is_ok());
+    assert_eq!(b"oops\n".to_vec(), invocation.stderr);
+
+    let log = fs::read_to_string(log_path.join("noisy.log")).expect("Cannot read guest log");
+    assert_eq!(format!("[{}] oops\n", invocation.id), log);
+
+    let mut capture = StderrCapture::new(4);
+    capture.write_all(b"truncated").unwrap();
+    assert_eq!(b"trun".to_vec(), capture.take());
+}
+
+#[test]
+fn test_metadata_env() {
+    let (_module_manager, handle) = common::deploy("runtime-metadata", &[("env", common::ENV_WAT)]);
+    let mut frame = DataFrame::new(vec![]);
+    frame.metadata.sender = "billing".to_string();
+    frame.metadata.headers.insert("trace".to_string(), "abc".to_string());
+    frame.metadata.content_type = Some("application/json".to_string());
+    frame.metadata.entrypoint = Some("enrich".to_string());
+    let invocation = handle.run(&frame);
+    let output = invocation.result.expect("Cannot run env module");
+
+    let env = String::from_utf8(output.body).expect("Env is not utf-8");
+    let env = env.split('\0').collect::<Vec<&str>>();
+    assert!(env.contains(&"WASM_CENTRAL_SENDER=billing"));
+    assert!(env.contains(&"WASM_CENTRAL_HEADERS={\"trace\":\"abc\"}"));
+    assert!(env.contains(&"WASM_CENTRAL_CONTENT_TYPE=application/json"));
+    assert!(env.contains(&"WASM_CENTRAL_ENTRYPOINT=enrich"));
+    assert!(env.contains(&format!("WASM_CENTRAL_INVOCATION_ID={}", invocation.id).as_str()));
+    assert!(!env.iter().any(|var| var.starts_with("WASM_CENTRAL_DEADLINE_MS=")));
+}
+
+/// Executions through the pre-instantiated unit beat linking the module on every call, both
+/// running on engines with the same configuration
+#[test]
+fn test_pre_instantiation() {
+    const ITERATIONS: u32 = 200;
+    let body = b"{\"a\": 1}".to_vec();
+
+    let (compiler, executor) = new_pair();
+    let compilation_unit = Some(
+        compiler
+            .compile(&mut common::ECHO_WAT.as_bytes())
+            .expect("Cannot compile echo module"),
+    );
+    let limits = ExecutionLimits::default();
+    let stderr = StderrCapture::new(1024);
+    let t_start = Instant::now();
+    for _ in 0..ITERATIONS {
+        executor
+            .execute(&compilation_unit, &limits, DataFrame::new(body.clone()), &stderr)
+            .expect("Cannot execute echo module");
+    }
+    let pre_instantiated = t_start.elapsed() / ITERATIONS;
+
+    let engine = Engine::new(&engine_config()).expect("WASM engine");
+    let module = Module::new(&engine, common::ECHO_WAT).expect("Cannot compile echo module");
+    let t_start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let wasi_ctx = WasiCtxBuilder::new()
+            .stdin(Box::new(ReadPipe::from(body.clone())))
+            .stdout(Box::new(WritePipe::new_in_memory()))
+            .build();
+        let mut store = Store::new(&engine, wasi_ctx);
+        store.add_fuel(u64::MAX).unwrap();
+        store.set_epoch_deadline(u64::MAX / 2);
+        let mut linker = Linker::new(&engine);
+        wasmtime_wasi::add_to_linker(&mut linker, |s| s).unwrap();
+        linker.module(&mut store, "", &module).unwrap();
+        linker
+            .get_default(&mut store, "")
+            .unwrap()
+            .typed::<(), (), _>(&store)
+            .unwrap()
+            .call(&mut store, ())
+            .unwrap();
+    }
+    let linked_per_call = t_start.elapsed() / ITERATIONS;
+
+    assert!(
+        pre_instantiated < linked_per_call,
+        "pre-instantiated: {:?}/call, linked per call: {:?}/call",
+        pre_instantiated,
+        linked_per_call
+    );
+}
@@ -0,0 +1,76 @@
+pub mod common;
+
+use wasm_central_runner::data::DataFrame;
+use wasm_central_runner::runner::ExecutionError;
+use wasm_central_runner::schema::{ModuleSchemas, SchemaFormat};
+
+use serde_json::json;
+
+#[test]
+fn test_schema_validation() {
+    let schema = common::order_schema(SchemaFormat::JsonSchema);
+
+    let valid = json!({"id": 1, "lines": [{"sku": "a", "quantity": 2}], "extra": true});
+    assert_eq!(Ok(()), schema.validate(&valid));
+
+    let invalid = json!({"id": 1, "note": 3, "lines": []});
+    let err = schema.validate(&invalid).unwrap_err();
+    assert_eq!("$.note", err.path);
+
+    let invalid = json!({"id": 1, "lines": [{"sku": "a", "quantity": 2}, {"sku": "b"}]});
+    let err = schema.validate(&invalid).unwrap_err();
+    assert_eq!("$.lines[1].quantity: expected sint32, found null", err.to_string());
+}
+
+#[test]
+fn test_module_schemas() {
+    let (module_manager, _) = common::deploy("runtime-schema", &[("echo", common::ECHO_WAT)]);
+    module_manager.set_module_schemas(
+        "echo",
+        ModuleSchemas {
+            input: Some(common::order_schema(SchemaFormat::JsonSchema)),
+            ..ModuleSchemas::default()
+        },
+    );
+
+    let handle = module_manager
+        .get_handle(&"echo".to_string())
+        .expect("Echo module is not deployed");
+    let invocation = handle.run(&DataFrame::new(br#"{"id": "1", "lines": []}"#.to_vec()));
+    assert!(matches!(invocation.result, Err(ExecutionError::InvalidInput(_))));
+
+    let invocation = handle.run(&DataFrame::new(br#"{"id": 1, "lines": []}"#.to_vec()));
+    assert!(invocation.result.is_ok());
+
+    // {"id": "1", "lines": []} as MessagePack
+    let mut frame = DataFrame::new(vec![0x82, 0xa2, b'i', b'd', 0xa1, b'1', 0xa5, b'l', b'i', b'n', b'e', b's', 0x90]);
+    frame.metadata.content_type = Some("application/msgpack".to_string());
+    assert!(matches!(handle.run(&frame).result, Err(ExecutionError::InvalidInput(_))));
+
+    let mut frame = DataFrame::new(br#"{"id": "1", "lines": []}"#.to_vec());
+    frame.metadata.content_type = Some("text/plain".to_string());
+    assert!(matches!(handle.run(&frame).result, Err(ExecutionError::InvalidInput(_))));
+
+    module_manager.set_module_schemas(
+        "echo",
+        ModuleSchemas {
+            output: Some(common::order_schema(SchemaFormat::JsonSchema)),
+            ..ModuleSchemas::default()
+        },
+    );
+    let handle = module_manager
+        .get_handle(&"echo".to_string())
+        .expect("Echo module is not deployed");
+    let invocation = handle.run(&DataFrame::new(br#"{"id": 1}"#.to_vec()));
+    assert!(matches!(invocation.result, Err(ExecutionError::InvalidOutput(_))));
+
+    // the guest writes the format it reads, {"id": 1, "lines": []} here
+    let mut frame = DataFrame::new(vec![0x82, 0xa2, b'i', b'd', 0x01, 0xa5, b'l', b'i', b'n', b'e', b's', 0x90]);
+    frame.metadata.content_type = Some("application/msgpack".to_string());
+    let output = handle.run(&frame).result.expect("Cannot run echo");
+    assert_eq!(frame.body, output.body);
+
+    let mut frame = DataFrame::new(b"plain".to_vec());
+    frame.metadata.content_type = Some("text/plain".to_string());
+    assert!(matches!(handle.run(&frame).result, Err(ExecutionError::InvalidOutput(_))));
+}
@@ -0,0 +1,92 @@
+pub mod common;
+
+use wasm_central_runner::data::DataFrame;
+use wasm_central_runner::routing::{SplitMode, TrafficSplit};
+use wasm_central_runner::runner::ExecutionError;
+use wasm_central_runner::shadow::{json_diff, DifferenceKind, ShadowReport};
+
+use serde_json::json;
+
+#[test]
+fn test_json_diff() {
+    let active = json!({"id": 1, "price": 2.0, "lines": [{"sku": "a"}, {"sku": "b"}], "note": "x"});
+    let candidate = json!({"id": 1, "price": 2, "lines": [{"sku": "a"}, {"sku": "c"}, {"sku": "d"}], "tax": 0});
+    let differences = json_diff(&active, &candidate)
+        .into_iter()
+        .map(|difference| (difference.path, difference.kind))
+        .collect::<Vec<(String, DifferenceKind)>>();
+    assert_eq!(
+        vec![
+            ("$.lines[1].sku".to_string(), DifferenceKind::Changed),
+            ("$.lines[2]".to_string(), DifferenceKind::Added),
+            ("$.note".to_string(), DifferenceKind::Removed),
+            ("$.tax".to_string(), DifferenceKind::Added),
+        ],
+        differences
+    );
+    assert!(json_diff(&active, &active).is_empty());
+
+    // both round to the same f64
+    let differences = json_diff(&json!({"id": 9007199254740993u64}), &json!({"id": 9007199254740992u64}));
+    assert_eq!(1, differences.len());
+    assert_eq!("$.id", differences[0].path);
+    assert_eq!(1, json_diff(&json!(u64::MAX), &json!(-1)).len());
+}
+
+#[test]
+fn test_shadow_report() {
+    let report = ShadowReport::new("2.0.0".to_string());
+    let output = |body: &str| DataFrame::new(body.as_bytes().to_vec());
+
+    report.record(1, &output(r#"{"id": 1}"#), &Ok(output(r#"{"id": 1}"#)));
+    report.record(2, &output(r#"{"lines": [1, 2]}"#), &Ok(output(r#"{"lines": [1, 3, 4]}"#)));
+    report.record(3, &output(r#"{"lines": [5]}"#), &Ok(output(r#"{"lines": [6]}"#)));
+    report.record(4, &output("plain"), &Ok(output("plain")));
+    report.record(5, &output("{}"), &Err(ExecutionError::InvalidOutput("$: expected object".to_string())));
+
+    let snapshot = report.snapshot();
+    assert_eq!(5, snapshot.compared);
+    assert_eq!(1, snapshot.identical);
+    assert_eq!(2, snapshot.different);
+    assert_eq!(1, snapshot.skipped);
+    assert_eq!(1, snapshot.candidate_failures);
+    assert_eq!(Some(&2), snapshot.path_counts.get("$.lines[*]"));
+    assert_eq!(vec![2, 3], snapshot.samples.iter().map(|sample| sample.invocation_id).collect::<Vec<u64>>());
+    assert_eq!(2, snapshot.samples[0].differences.len());
+}
+
+#[test]
+fn test_shadow_routing() {
+    let (module_manager, _) = common::deploy(
+        "runtime-shadow",
+        &[("echo", common::ECHO_WAT), ("echo.manifest.json", r#"{"version": "1.0.0"}"#)],
+    );
+    common::write_version(&common::runtime_path("runtime-shadow"), common::JSON_WAT, "2.0.0");
+    module_manager.tick();
+    module_manager.rollback("echo").expect("Cannot roll back echo");
+
+    module_manager
+        .set_traffic_split("echo", TrafficSplit::new("2.0.0".to_string(), 100, SplitMode::Shadow))
+        .expect("Cannot shadow echo");
+    let route = module_manager.route("echo").unwrap();
+    assert!(route.mirror.is_none());
+    let shadow = route.shadow.expect("Call is not shadowed");
+
+    let frame = DataFrame::new(br#"{"id":1,"tags":["a"]}"#.to_vec());
+    let invocation = route.handle.run(&frame);
+    let output = invocation.result.expect("Cannot run echo");
+    assert_eq!(frame.body, output.body);
+    shadow.compare(&frame, invocation.id, &output);
+
+    let snapshot = module_manager.shadow_report("echo").unwrap().snapshot();
+    assert_eq!("2.0.0", snapshot.candidate_version);
+    assert_eq!(1, snapshot.different);
+    assert_eq!(Some(&1), snapshot.path_counts.get("$.extra"));
+    assert_eq!(Some(&1), snapshot.path_counts.get("$.tags[*]"));
+    assert_eq!(invocation.id, snapshot.samples[0].invocation_id);
+
+    // the report outlives its split
+    module_manager.clear_traffic_split("echo");
+    assert!(module_manager.route("echo").unwrap().shadow.is_none());
+    assert_eq!(1, module_manager.shadow_report("echo").unwrap().snapshot().compared);
+}
@@ -0,0 +1,188 @@
+pub mod common;
+
+use wasm_central_runner::data::DataFrame;
+use wasm_central_runner::runner::ExecutionError;
+use wasm_central_runner::schema::{Field, ModuleSchemas, Record, Schema, SchemaFormat};
+use wasm_central_runner::transcode::{from_json, to_json, to_value, PayloadFormat};
+
+use serde_json::{json, Value};
+
+fn roundtrip(format: PayloadFormat, schema: &Schema, value: &Value) -> Value {
+    let encoded = from_json(format, schema, &serde_json::to_vec(value).unwrap()).expect("Cannot encode");
+    let decoded = to_json(format, schema, &encoded).expect("Cannot decode");
+    serde_json::from_slice(&decoded).unwrap()
+}
+
+#[test]
+fn test_avro_transcoding() {
+    let schema = Schema {
+        format: SchemaFormat::Avro,
+        records: vec![Record {
+            name: "User".to_string(),
+            fields: vec![common::field("id", "long"), common::field("name", "string")],
+        }],
+    };
+    let decoded = to_json(PayloadFormat::Avro, &schema, &[0x02, 0x04, b'a', b'b']).expect("Cannot decode");
+    assert_eq!(json!({"id": 1, "name": "ab"}), serde_json::from_slice::<Value>(&decoded).unwrap());
+
+    let schema = common::order_schema(SchemaFormat::Avro);
+    let order = json!({
+        "id": -7,
+        "note": null,
+        "lines": [{"sku": "a", "quantity": -2, "tag": "\u{00ff}\u{0000}"}],
+        "weights": [1.5, 2.0]
+    });
+    assert_eq!(order, roundtrip(PayloadFormat::Avro, &schema, &order));
+
+    let err = from_json(PayloadFormat::Avro, &schema, br#"{"id": 1, "lines": [{"sku": 1}]}"#).unwrap_err();
+    assert_eq!("$.lines[0].sku", err.path);
+}
+
+#[test]
+fn test_protobuf_transcoding() {
+    let schema = Schema {
+        format: SchemaFormat::Protobuf,
+        records: vec![Record {
+            name: "Test".to_string(),
+            fields: vec![common::field("a", "int32"), common::field("b", "string")],
+        }],
+    };
+    let decoded = to_json(PayloadFormat::Protobuf, &schema, &[0x08, 0x96, 0x01]).expect("Cannot decode");
+    assert_eq!(json!({"a": 150, "b": ""}), serde_json::from_slice::<Value>(&decoded).unwrap());
+
+    // declared field numbers win over positions
+    let numbered = Schema {
+        format: SchemaFormat::Protobuf,
+        records: vec![Record {
+            name: "Test".to_string(),
+            fields: vec![
+                Field {
+                    number: Some(2),
+                    ..common::field("b", "string")
+                },
+                Field {
+                    number: Some(1),
+                    ..common::field("a", "int32")
+                },
+            ],
+        }],
+    };
+    let decoded = to_json(PayloadFormat::Protobuf, &numbered, &[0x08, 0x96, 0x01]).expect("Cannot decode");
+    assert_eq!(json!({"a": 150, "b": ""}), serde_json::from_slice::<Value>(&decoded).unwrap());
+    let encoded = from_json(PayloadFormat::Protobuf, &numbered, br#"{"a": 150}"#).expect("Cannot encode");
+    assert_eq!(vec![0x08, 0x96, 0x01], encoded);
+
+    // 32-bit types are range checked rather than truncated, both ways
+    let narrow = Schema {
+        format: SchemaFormat::Protobuf,
+        records: vec![Record {
+            name: "Test".to_string(),
+            fields: vec![common::field("a", "fixed32"), common::field("b", "int32")],
+        }],
+    };
+    let err = from_json(PayloadFormat::Protobuf, &narrow, br#"{"a": 4294967296}"#).unwrap_err();
+    assert_eq!("$.a", err.path);
+    let err = from_json(PayloadFormat::Protobuf, &narrow, br#"{"b": 2147483648}"#).unwrap_err();
+    assert_eq!("$.b", err.path);
+    let err = to_json(PayloadFormat::Protobuf, &narrow, &[0x10, 0x80, 0x80, 0x80, 0x80, 0x10]).unwrap_err();
+    assert_eq!("$.b", err.path);
+    let value = json!({"a": 4294967295u32, "b": -1});
+    assert_eq!(value, roundtrip(PayloadFormat::Protobuf, &narrow, &value));
+
+    let schema = common::order_schema(SchemaFormat::Protobuf);
+    let order = json!({
+        "id": 42,
+        "note": "fragile",
+        "lines": [{"sku": "a", "quantity": -2, "tag": ""}, {"sku": "b", "quantity": 3, "tag": "x"}],
+        "weights": [0.5]
+    });
+    assert_eq!(order, roundtrip(PayloadFormat::Protobuf, &schema, &order));
+
+    let truncated = to_json(PayloadFormat::Protobuf, &schema, &[0x08]).unwrap_err();
+    assert_eq!("$.id", truncated.path);
+}
+
+#[test]
+fn test_transcoding_bounds() {
+    let node = Record {
+        name: "Node".to_string(),
+        fields: vec![common::field("value", "long"), common::field("next", "Node?")],
+    };
+    let schema = Schema {
+        format: SchemaFormat::Avro,
+        records: vec![node],
+    };
+    let err = to_json(PayloadFormat::Avro, &schema, &[0x02, 0x00]).unwrap_err();
+    assert_eq!("record Node references itself", err.message);
+    let err = to_json(PayloadFormat::Protobuf, &schema, &[0x08, 0x01]).unwrap_err();
+    assert_eq!("record Node references itself", err.message);
+
+    let chain = (0..40)
+        .map(|i| Record {
+            name: format!("R{}", i),
+            fields: vec![common::field("next", &format!("R{}?", i + 1))],
+        })
+        .collect();
+    let schema = Schema {
+        format: SchemaFormat::Protobuf,
+        records: chain,
+    };
+    let err = to_json(PayloadFormat::Protobuf, &schema, &[]).unwrap_err();
+    assert_eq!("records nest deeper than 32 levels", err.message);
+
+    // a single block claiming 2^62 null items
+    let schema = Schema {
+        format: SchemaFormat::Avro,
+        records: vec![Record {
+            name: "Nulls".to_string(),
+            fields: vec![common::field("items", "null[]")],
+        }],
+    };
+    let count = [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01];
+    let err = to_json(PayloadFormat::Avro, &schema, &count).unwrap_err();
+    assert_eq!("$.items", err.path);
+}
+
+#[test]
+fn test_payload_format() {
+    assert_eq!(Some(PayloadFormat::Json), PayloadFormat::from_content_type(None));
+    assert_eq!(Some(PayloadFormat::Avro), PayloadFormat::from_content_type(Some("application/avro")));
+    assert_eq!(Some(PayloadFormat::Protobuf), PayloadFormat::from_content_type(Some("application/x-protobuf")));
+    assert_eq!(Some(PayloadFormat::MessagePack), PayloadFormat::from_content_type(Some("application/msgpack")));
+    assert_eq!(Some(PayloadFormat::Cbor), PayloadFormat::from_content_type(Some("application/cbor")));
+    assert_eq!(None, PayloadFormat::from_content_type(Some("text/plain")));
+
+    let msgpack = from_json(PayloadFormat::MessagePack, &Schema::default(), br#"{"id":1}"#).unwrap();
+    assert_eq!(json!({"id": 1}), to_value(PayloadFormat::MessagePack, &msgpack).unwrap());
+    assert!(to_value(PayloadFormat::Avro, &msgpack).is_err());
+}
+
+#[test]
+fn test_handle_transcoding() {
+    let (module_manager, _) = common::deploy("runtime-transcode", &[("echo", common::ECHO_WAT)]);
+    module_manager.set_module_schemas(
+        "echo",
+        ModuleSchemas {
+            input: Some(common::order_schema(SchemaFormat::Avro)),
+            ..ModuleSchemas::default()
+        },
+    );
+    let handle = module_manager
+        .get_handle(&"echo".to_string())
+        .expect("Echo module is not deployed");
+
+    let order = json!({"id": 1, "note": "a", "lines": [], "weights": [1.0]});
+    let schema = common::order_schema(SchemaFormat::Avro);
+    let body = from_json(PayloadFormat::Avro, &schema, &serde_json::to_vec(&order).unwrap()).unwrap();
+    let mut frame = DataFrame::new(body.clone());
+    frame.metadata.content_type = Some("application/avro".to_string());
+
+    let output = handle.run(&frame).result.expect("Cannot run echo");
+    assert_eq!(body, output.body);
+    assert_eq!(Some("application/avro".to_string()), output.metadata.content_type);
+
+    let mut truncated = DataFrame::new(vec![0x08]);
+    truncated.metadata.content_type = Some("application/x-protobuf".to_string());
+    let invocation = handle.run(&truncated);
+    assert!(matches!(invocation.result, Err(ExecutionError::InvalidInput(_))));
+}
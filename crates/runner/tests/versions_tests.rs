@@ -0,0 +1,45 @@
+pub mod common;
+
+use wasm_central_runner::data::DataFrame;
+use wasm_central_runner::functions::{FunctionManager, FunctionManagerError};
+use wasm_central_runner::runner::ExecutionError;
+
+fn run(module_manager: &FunctionManager, module_name: &str) -> Vec<u8> {
+    let handle = module_manager.handle(module_name).expect("Module is not deployed");
+    handle.run(&DataFrame::new(b"ping".to_vec())).result.unwrap().body
+}
+
+#[test]
+fn test_versions_and_rollback() {
+    let (module_manager, _) = common::deploy(
+        "runtime-versions",
+        &[("echo", common::ECHO_WAT), ("echo.manifest.json", r#"{"version": "1.0.0"}"#)],
+    );
+    common::write_version(&common::runtime_path("runtime-versions"), common::ENV_WAT, "2.0.0");
+    module_manager.tick();
+
+    let versions = module_manager
+        .versions("echo")
+        .into_iter()
+        .map(|module| module.version)
+        .collect::<Vec<String>>();
+    assert_eq!(vec!["1.0.0".to_string(), "2.0.0".to_string()], versions);
+    assert_ne!(b"ping".to_vec(), run(&module_manager, "echo"));
+    assert_eq!(b"ping".to_vec(), run(&module_manager, "echo@1.0.0"));
+    assert!(matches!(module_manager.handle("echo@3.0.0"), Err(ExecutionError::ModuleNotFound(_))));
+
+    assert_eq!("1.0.0", module_manager.rollback("echo").unwrap());
+    assert_eq!(b"ping".to_vec(), run(&module_manager, "echo"));
+
+    // the file still holds 2.0.0, the rollback survives the next tick
+    module_manager.tick();
+    assert_eq!(b"ping".to_vec(), run(&module_manager, "echo"));
+    assert!(matches!(
+        module_manager.rollback("echo"),
+        Err(FunctionManagerError::NoPreviousVersion(_))
+    ));
+    assert!(matches!(
+        module_manager.rollback("unknown"),
+        Err(FunctionManagerError::UnavailableModule(_))
+    ));
+}
@@ -0,0 +1,57 @@
+use anyhow::Result;
+use quickjs_wasm_rs::{json, Context, Deserializer, Serializer, Value};
+
+/// Encoding of the input and output bodies, picked from the invocation content type
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Codec {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Codec {
+    /// Unknown content types fall back to JSON
+    pub fn from_content_type(content_type: Option<&str>) -> Codec {
+        match content_type {
+            Some(content_type) if content_type.contains("msgpack") || content_type.contains("messagepack") => {
+                Codec::MessagePack
+            }
+            Some(content_type) if content_type.contains("cbor") => Codec::Cbor,
+            _ => Codec::Json,
+        }
+    }
+
+    pub fn decode(&self, context: &Context, bytes: &[u8]) -> Result<Value> {
+        let mut serializer = Serializer::from_context(context)?;
+        match self {
+            Codec::Json => return json::transcode_input(context, bytes),
+            Codec::MessagePack => {
+                let mut deserializer = rmp_serde::Deserializer::new(bytes);
+                serde_transcode::transcode(&mut deserializer, &mut serializer)?;
+            }
+            Codec::Cbor => {
+                let mut deserializer = serde_cbor::Deserializer::from_slice(bytes);
+                serde_transcode::transcode(&mut deserializer, &mut serializer)?;
+            }
+        }
+        Ok(serializer.value)
+    }
+
+    pub fn encode(&self, value: Value) -> Result<Vec<u8>> {
+        let mut output = vec![];
+        match self {
+            Codec::Json => return json::transcode_output(value),
+            Codec::MessagePack => {
+                let mut deserializer = Deserializer::from(value);
+                let mut serializer = rmp_serde::Serializer::new(&mut output);
+                serde_transcode::transcode(&mut deserializer, &mut serializer)?;
+            }
+            Codec::Cbor => {
+                let mut deserializer = Deserializer::from(value);
+                let mut serializer = serde_cbor::Serializer::new(serde_cbor::ser::IoWrite::new(&mut output));
+                serde_transcode::transcode(&mut deserializer, &mut serializer)?;
+            }
+        }
+        Ok(output)
+    }
+}
@@ -1,5 +1,12 @@
+mod codec;
 mod engine;
+mod metadata;
+mod payload;
+mod promise;
 
+use codec::Codec;
+use payload::{InputType, RawPayloads};
+use promise::Promises;
 use quickjs_wasm_rs::{json, Context, Value};
 use std::fs;
 
@@ -12,8 +19,18 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 static mut JS_CONTEXT: OnceCell<Context> = OnceCell::new();
 static mut ENTRYPOINT: (OnceCell<Value>, OnceCell<Value>) = (OnceCell::new(), OnceCell::new());
+static mut INPUT_TYPE: OnceCell<InputType> = OnceCell::new();
+static mut RAW_PAYLOADS: OnceCell<RawPayloads> = OnceCell::new();
+static mut PROMISES: OnceCell<Promises> = OnceCell::new();
 static SCRIPT_NAME: &str = "script.js";
 
+/// Exit codes reported to the runner, 0 being a successful call
+const EXIT_SCRIPT_ERROR: i32 = 1;
+const EXIT_INVALID_INPUT: i32 = 2;
+const EXIT_INVALID_OUTPUT: i32 = 3;
+const EXIT_IO_ERROR: i32 = 4;
+const EXIT_UNKNOWN_ENTRYPOINT: i32 = 5;
+
 // TODO
 //
 // AOT validations:
@@ -36,7 +53,29 @@ pub extern "C" fn init() {
             } else {
                 let global = context.global_object().unwrap();
                 if let Ok(ns_object) = global.get_property("Namespace") {
-                    if let Ok(main) = ns_object.get_property("main") {
+                    // scripts not declaring an input type get JSON values
+                    let input_type = match ns_object.get_property("inputType") {
+                        Ok(value) if value.is_str() => value.as_str().ok().and_then(InputType::from_name),
+                        _ => Some(InputType::Json),
+                    };
+                    if input_type.is_none() {
+                        eprintln!("Unknown inputType, expected json, string or bytes");
+                    } else if let Ok(main) = ns_object.get_property("main") {
+                        if input_type != Some(InputType::Json) {
+                            match RawPayloads::new(&context) {
+                                Ok(raw_payloads) => {
+                                    RAW_PAYLOADS.set(raw_payloads).ok();
+                                }
+                                Err(_) => eprintln!("Cannot eval bytes helpers"),
+                            }
+                        }
+                        match Promises::new(&context) {
+                            Ok(promises) => {
+                                PROMISES.set(promises).ok();
+                            }
+                            Err(_) => eprintln!("Cannot eval promise helpers"),
+                        }
+                        INPUT_TYPE.set(input_type.unwrap()).unwrap();
                         JS_CONTEXT.set(context).unwrap();
                         ENTRYPOINT.0.set(ns_object).unwrap();
                         ENTRYPOINT.1.set(main).unwrap();
@@ -51,27 +90,69 @@ pub extern "C" fn init() {
     }
 }
 
+/// `Namespace.handlers[name]` along with the handlers object it gets called on
+fn handler(namespace: &Value, name: &str) -> anyhow::Result<(Value, Value)> {
+    let handlers = namespace.get_property("handlers")?;
+    if !handlers.is_object() {
+        anyhow::bail!("Namespace.handlers is not an object");
+    }
+    let handler = handlers.get_property(name)?;
+    if !handler.is_function() {
+        anyhow::bail!("Namespace.handlers.{} is not a function", name);
+    }
+    Ok((handlers, handler))
+}
+
+/// Reports `message` on stderr and ends the call with `code`
+fn fail(code: i32, message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(code)
+}
+
 fn main() {
     unsafe {
         let context = JS_CONTEXT.get().expect("JS context");
         let receiver = ENTRYPOINT.0.get().expect("Entrypoint");
         let main = ENTRYPOINT.1.get().expect("main function");
-        let input_bytes = engine::load().expect("Couldn't load input");
+        let input_bytes = engine::load()
+            .unwrap_or_else(|err| fail(EXIT_IO_ERROR, format!("Cannot read input because {}", err)));
 
-        match json::transcode_input(context, &input_bytes) {
-            Ok(input_value) => {
-                let output_value = main.call(receiver, &[input_value]);
+        let metadata = metadata::load();
+        let codec = Codec::from_content_type(metadata.get("contentType").and_then(|value| value.as_str()));
 
-                if output_value.is_err() {
-                    panic!("{}", output_value.unwrap_err().to_string());
-                }
+        let input_type = INPUT_TYPE.get().copied().unwrap_or(InputType::Json);
+        let raw_payloads = || RAW_PAYLOADS.get().expect("Bytes helpers");
 
-                let output = json::transcode_output(output_value.unwrap()).unwrap();
-                engine::store(&output).expect("Couldn't store output");
-            }
-            Err(err) => {
-                eprintln!("Cannot read json because {}", err);
-            }
+        let input_value = match input_type {
+            InputType::Json => codec.decode(context, &input_bytes),
+            _ => raw_payloads().input(context, input_type, &input_bytes),
+        }
+        .unwrap_or_else(|err| fail(EXIT_INVALID_INPUT, format!("Cannot read {:?} input because {}", input_type, err)));
+        let metadata_bytes = serde_json::to_vec(&metadata).unwrap();
+        let metadata_value = json::transcode_input(context, &metadata_bytes)
+            .unwrap_or_else(|err| fail(EXIT_INVALID_INPUT, format!("Cannot read metadata because {}", err)));
+        // calls without an entrypoint go to `Namespace.main`
+        let named_handler = metadata
+            .get("entrypoint")
+            .and_then(|value| value.as_str())
+            .map(|name| {
+                handler(receiver, name)
+                    .unwrap_or_else(|err| fail(EXIT_UNKNOWN_ENTRYPOINT, format!("Unknown entrypoint {} because {}", name, err)))
+            });
+        let (receiver, main) = match &named_handler {
+            Some((handlers, handler)) => (handlers, handler),
+            None => (receiver, main),
+        };
+        let output_value = main
+            .call(receiver, &[input_value, metadata_value])
+            .and_then(|value| PROMISES.get().expect("Promise helpers").resolve(context, value))
+            .unwrap_or_else(|err| fail(EXIT_SCRIPT_ERROR, format!("Script failed because {}", err)));
+        let output = match input_type {
+            InputType::Json => codec.encode(output_value),
+            _ => raw_payloads().output(output_value),
         }
+        .unwrap_or_else(|err| fail(EXIT_INVALID_OUTPUT, format!("Cannot write {:?} output because {}", input_type, err)));
+        engine::store(&output)
+            .unwrap_or_else(|err| fail(EXIT_IO_ERROR, format!("Cannot write output because {}", err)));
     }
 }
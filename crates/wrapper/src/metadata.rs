@@ -0,0 +1,42 @@
+use serde_json::{Map, Value};
+use std::env;
+
+/// Invocation metadata the runner exposes as WASM_CENTRAL_* environment variables, as the JSON
+/// object handed over to `Namespace.main` next to the input
+pub fn load() -> Value {
+    let mut metadata = Map::new();
+    for (name, value) in env::vars() {
+        let value = match name.as_str() {
+            "WASM_CENTRAL_SENDER" => Value::String(value),
+            "WASM_CENTRAL_CONTENT_TYPE" => Value::String(value),
+            "WASM_CENTRAL_ENTRYPOINT" => Value::String(value),
+            "WASM_CENTRAL_HEADERS" | "WASM_CENTRAL_SCHEMA" => {
+                serde_json::from_str(&value).unwrap_or(Value::Null)
+            }
+            "WASM_CENTRAL_INVOCATION_ID" | "WASM_CENTRAL_DEADLINE_MS" => {
+                value.parse::<u64>().map(Value::from).unwrap_or(Value::Null)
+            }
+            _ => continue,
+        };
+        metadata.insert(property_name(&name["WASM_CENTRAL_".len()..]), value);
+    }
+    Value::Object(metadata)
+}
+
+/// `CONTENT_TYPE` to `contentType`
+fn property_name(variable: &str) -> String {
+    let mut property = String::new();
+    for (i, word) in variable.split('_').enumerate() {
+        let word = word.to_lowercase();
+        if i == 0 {
+            property.push_str(&word);
+        } else {
+            let mut chars = word.chars();
+            if let Some(first) = chars.next() {
+                property.extend(first.to_uppercase());
+                property.push_str(chars.as_str());
+            }
+        }
+    }
+    property
+}
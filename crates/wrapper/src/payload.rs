@@ -0,0 +1,69 @@
+use anyhow::{anyhow, bail, Result};
+use quickjs_wasm_rs::{Context, Value};
+
+/// Conversions between ArrayBuffers and typed arrays, simpler to express on the JS side
+const BYTES_HELPERS: &str = "({
+    toView: (buffer) => new Uint8Array(buffer),
+    toBuffer: (value) => ArrayBuffer.isView(value)
+        ? value.buffer.slice(value.byteOffset, value.byteOffset + value.byteLength)
+        : value,
+})";
+
+/// What `Namespace.main` gets its input as, declared by the script through `Namespace.inputType`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputType {
+    /// Decoded value of a JSON, MessagePack or CBOR body, the default
+    Json,
+    /// UTF-8 body as a string
+    String,
+    /// Body as a Uint8Array
+    Bytes,
+}
+
+impl InputType {
+    pub fn from_name(name: &str) -> Option<InputType> {
+        match name {
+            "json" => Some(InputType::Json),
+            "string" => Some(InputType::String),
+            "bytes" => Some(InputType::Bytes),
+            _ => None,
+        }
+    }
+}
+
+/// Bodies handed over to the script as they are, for the string and bytes input types
+pub struct RawPayloads {
+    helpers: Value,
+}
+
+impl RawPayloads {
+    pub fn new(context: &Context) -> Result<RawPayloads> {
+        let helpers = context.eval_global("bytes.js", BYTES_HELPERS)?;
+        Ok(RawPayloads { helpers })
+    }
+
+    pub fn input(&self, context: &Context, input_type: InputType, bytes: &[u8]) -> Result<Value> {
+        match input_type {
+            InputType::String => {
+                let string = std::str::from_utf8(bytes).map_err(|err| anyhow!("input is not UTF-8: {}", err))?;
+                context.value_from_str(string)
+            }
+            _ => {
+                let buffer = context.array_buffer_value(bytes)?;
+                self.helpers.get_property("toView")?.call(&self.helpers, &[buffer])
+            }
+        }
+    }
+
+    /// Either input type can return a string, an ArrayBuffer or a typed array
+    pub fn output(&self, value: Value) -> Result<Vec<u8>> {
+        let value = self.helpers.get_property("toBuffer")?.call(&self.helpers, &[value])?;
+        if value.is_str() {
+            Ok(value.as_str()?.as_bytes().to_vec())
+        } else if value.is_array_buffer() {
+            Ok(value.as_bytes()?.to_vec())
+        } else {
+            bail!("expected a string, an ArrayBuffer or a typed array")
+        }
+    }
+}
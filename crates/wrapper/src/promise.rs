@@ -0,0 +1,49 @@
+use anyhow::{bail, Result};
+use quickjs_wasm_rs::{Context, Value};
+
+/// Records how the value returned by the script settles, plain values settling right away
+const SETTLE_HELPER: &str = "((value) => {
+    const outcome = { settled: !(value instanceof Promise), rejected: false, value: value };
+    if (!outcome.settled) {
+        value.then(
+            (result) => {
+                outcome.settled = true;
+                outcome.value = result;
+            },
+            (error) => {
+                outcome.settled = true;
+                outcome.rejected = true;
+                outcome.reason = error instanceof Error
+                    ? `${error.name}: ${error.message}\\n${error.stack || ''}`
+                    : String(error);
+            });
+    }
+    return outcome;
+})";
+
+/// Waits on Promises returned by the script by running the QuickJS job queue
+pub struct Promises {
+    settle: Value,
+}
+
+impl Promises {
+    pub fn new(context: &Context) -> Result<Promises> {
+        let settle = context.eval_global("settle.js", SETTLE_HELPER)?;
+        Ok(Promises { settle })
+    }
+
+    /// Value `value` resolves to, rejections being reported with the error message and stack.
+    /// There is no event loop, so Promises waiting on anything but other Promises never settle
+    pub fn resolve(&self, context: &Context, value: Value) -> Result<Value> {
+        let global = context.global_object()?;
+        let outcome = self.settle.call(&global, &[value])?;
+        context.execute_pending()?;
+        if !outcome.get_property("settled")?.as_bool()? {
+            bail!("Promise returned by the function never settled");
+        }
+        if outcome.get_property("rejected")?.as_bool()? {
+            bail!("Promise rejected with {}", outcome.get_property("reason")?.as_str()?);
+        }
+        outcome.get_property("value")
+    }
+}
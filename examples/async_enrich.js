@@ -0,0 +1,6 @@
+Namespace = {
+    main: async function enrich(input, metadata) {
+        const sender = await Promise.resolve(metadata.sender);
+        return { ...input, sender };
+    }
+}
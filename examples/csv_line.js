@@ -0,0 +1,8 @@
+Namespace = {
+    inputType: "string",
+    main: function upperCaseFirstColumn(line) {
+        const columns = line.split(",");
+        columns[0] = columns[0].toUpperCase();
+        return columns.join(",");
+    }
+}
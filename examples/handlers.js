@@ -0,0 +1,14 @@
+function normalize(input) {
+    return { ...input, name: (input.name || "").trim() };
+}
+
+Namespace = {
+    handlers: {
+        enrich: function enrich(input, metadata) {
+            return { ...normalize(input), sender: metadata.sender };
+        },
+        filter: function filter(input) {
+            return normalize(input).name.length > 0 ? input : null;
+        }
+    }
+}